@@ -0,0 +1,171 @@
+//! The "Now playing" card, posted where the music was requested whenever a queued track starts.
+//! There is one card per call, it is edited or replaced for the next track and removed when the
+//! queue runs out.
+
+use crate::Data;
+use crate::commands::ui::Theme;
+use crate::player::{GuildPlayer, queued_track};
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, CreateMessage, EditMessage, GuildId, MessageId};
+use serenity::async_trait;
+use songbird::tracks::{PlayMode, TrackHandle};
+use songbird::{Event, EventContext, EventHandler};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+struct Card {
+    channel_id: ChannelId,
+    message_id: MessageId,
+    track: TrackHandle,
+}
+
+#[derive(Clone)]
+pub struct Announcer {
+    ctx: serenity::Context,
+    data: Data,
+    guild_id: GuildId,
+    card: Arc<Mutex<Option<Card>>>,
+}
+
+impl Announcer {
+    pub fn new(ctx: serenity::Context, data: Data, guild_id: GuildId) -> Self {
+        Self {
+            ctx,
+            data,
+            guild_id,
+            card: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    async fn started(&self, track: &TrackHandle) {
+        let Some(queued) = queued_track(track).await else {
+            return;
+        };
+        // Restarted tracks were announced already, the card follows them to their new handle
+        if queued.retries > 0 {
+            if let Some(card) = self.card.lock().await.as_mut() {
+                card.track = track.clone();
+            }
+            return;
+        }
+        if !self
+            .data
+            .settings
+            .guild(self.guild_id)
+            .await
+            .announce_tracks
+        {
+            return;
+        }
+
+        let mut card = self.card.lock().await;
+        // Resumed after a pause
+        if card
+            .as_ref()
+            .is_some_and(|card| card.track.uuid() == track.uuid())
+        {
+            return;
+        }
+
+        let embed = Theme::of(&self.data.settings, Some(self.guild_id))
+            .await
+            .track_card(":notes: Now playing", &queued.metadata);
+
+        if let Some(old) = card.take() {
+            // Edited while nothing was posted below it, otherwise replaced so it isn't buried
+            let latest = self
+                .ctx
+                .cache
+                .channel(old.channel_id)
+                .is_some_and(|channel| channel.last_message_id == Some(old.message_id));
+            if latest && old.channel_id == queued.channel_id {
+                let edited = old
+                    .channel_id
+                    .edit_message(
+                        &self.ctx.http,
+                        old.message_id,
+                        EditMessage::new().embed(embed.clone()),
+                    )
+                    .await;
+                match edited {
+                    Ok(_) => {
+                        *card = Some(Card {
+                            track: track.clone(),
+                            ..old
+                        });
+                        return;
+                    }
+                    Err(e) => println!("Failed to update now playing card: {}", e),
+                }
+            }
+            let _ = old
+                .channel_id
+                .delete_message(&self.ctx.http, old.message_id)
+                .await;
+        }
+
+        match queued
+            .channel_id
+            .send_message(&self.ctx.http, CreateMessage::new().embed(embed))
+            .await
+        {
+            Ok(message) => {
+                *card = Some(Card {
+                    channel_id: queued.channel_id,
+                    message_id: message.id,
+                    track: track.clone(),
+                })
+            }
+            Err(e) => println!("Failed to post now playing card: {}", e),
+        }
+    }
+
+    async fn ended(&self, track: &TrackHandle) {
+        let mut card = self.card.lock().await;
+        if !card
+            .as_ref()
+            .is_some_and(|card| card.track.uuid() == track.uuid())
+        {
+            return;
+        }
+
+        // The next track takes the card over
+        let next = match GuildPlayer::get(&self.ctx, self.guild_id).await {
+            Some(player) => match player.queue().await {
+                Ok(queue) => queue
+                    .current_queue()
+                    .iter()
+                    .any(|queued| queued.uuid() != track.uuid()),
+                Err(_) => false,
+            },
+            None => false,
+        };
+        if next {
+            return;
+        }
+
+        if let Some(old) = card.take() {
+            let _ = old
+                .channel_id
+                .delete_message(&self.ctx.http, old.message_id)
+                .await;
+        }
+    }
+}
+
+#[async_trait]
+impl EventHandler for Announcer {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(track_events) = ctx {
+            for (state, track) in *track_events {
+                match state.playing {
+                    PlayMode::Play => self.started(track).await,
+                    PlayMode::End | PlayMode::Stop => self.ended(track).await,
+                    _ => {}
+                }
+            }
+        }
+
+        None
+    }
+}
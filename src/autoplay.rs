@@ -0,0 +1,201 @@
+//! Autoplay: when the last queued track ends, a related one is queued, taken from the YouTube mix of
+//! the track or a search for its artist and title. Tracks played during the call aren't picked again.
+
+use crate::Data;
+use crate::commands::music::play::{flat_playlist, get_ytdlp_args};
+use crate::player::{GuildPlayer, QueuedTrack, listeners, queued_track};
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use serenity::async_trait;
+use songbird::input::{AuxMetadata, Compose, YoutubeDl};
+use songbird::tracks::{PlayMode, TrackHandle};
+use songbird::{Event, EventContext, EventHandler};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// Played tracks remembered to avoid repeats.
+const HISTORY_LEN: usize = 100;
+
+/// The id of a YouTube video url.
+fn video_id(url: &str) -> Option<&str> {
+    let (_, rest) = url
+        .split_once("v=")
+        .or_else(|| url.split_once("youtu.be/"))?;
+    let id = &rest[..rest.find(['&', '?', '#']).unwrap_or(rest.len())];
+    (id.len() == 11).then_some(id)
+}
+
+/// How a track is remembered, by its video id where possible.
+fn history_key(url: &str) -> String {
+    video_id(url).unwrap_or(url).to_string()
+}
+
+/// Where related tracks of `metadata` are found, as understood by yt-dlp.
+fn related_source(metadata: &AuxMetadata) -> Option<String> {
+    if let Some(id) = metadata.source_url.as_deref().and_then(video_id) {
+        return Some(format!(
+            "https://www.youtube.com/watch?v={}&list=RD{}",
+            id, id
+        ));
+    }
+
+    let query = format!(
+        "{} {}",
+        metadata.artist.as_deref().unwrap_or_default(),
+        metadata.title.as_deref().unwrap_or_default()
+    );
+    let query = query.trim();
+    (!query.is_empty()).then(|| format!("ytsearch10:{}", query))
+}
+
+#[derive(Clone)]
+pub struct Autoplay {
+    ctx: serenity::Context,
+    data: Data,
+    guild_id: GuildId,
+    history: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl Autoplay {
+    pub fn new(ctx: serenity::Context, data: Data, guild_id: GuildId) -> Self {
+        Self {
+            ctx,
+            data,
+            guild_id,
+            history: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    async fn remember(&self, url: &str) {
+        let key = history_key(url);
+        let mut history = self.history.lock().await;
+        if history.contains(&key) {
+            return;
+        }
+        if history.len() == HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(key);
+    }
+
+    /// Whether a track other than `ended` is still queued.
+    async fn queue_continues(&self, ended: &TrackHandle) -> bool {
+        let Some(player) = GuildPlayer::get(&self.ctx, self.guild_id).await else {
+            return true;
+        };
+        match player.queue().await {
+            Ok(queue) => queue
+                .current_queue()
+                .iter()
+                .any(|queued| queued.uuid() != ended.uuid()),
+            Err(_) => true,
+        }
+    }
+
+    async fn queue_related(self, last: QueuedTrack) {
+        let Some(source) = related_source(&last.metadata) else {
+            return;
+        };
+        let candidates = match flat_playlist(&source).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                warn!("Failed to look up related tracks: {}", e);
+                return;
+            }
+        };
+
+        let url = {
+            let history = self.history.lock().await;
+            candidates
+                .into_iter()
+                .find(|url| !history.contains(&history_key(url)))
+        };
+        let Some(url) = url else {
+            info!("No related track left to autoplay");
+            return;
+        };
+
+        let mut source =
+            YoutubeDl::new(self.data.http_client.clone(), url.clone()).user_args(get_ytdlp_args());
+        let mut metadata = match source.aux_metadata().await {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Failed to fetch metadata of {}: {:?}", url, e);
+                return;
+            }
+        };
+        metadata.source_url.get_or_insert_with(|| url.clone());
+
+        // Someone may have queued something in the meantime
+        let Some(player) = GuildPlayer::get(&self.ctx, self.guild_id).await else {
+            return;
+        };
+        let idle = match player.queue().await {
+            Ok(queue) => queue.is_empty(),
+            Err(_) => false,
+        };
+        if !idle {
+            return;
+        }
+
+        info!("Autoplaying {}", url);
+        self.remember(&url).await;
+        if let Err(e) = player
+            .enqueue(source.into(), metadata, last.channel_id)
+            .await
+        {
+            warn!("Failed to autoplay {}: {}", url, e);
+        }
+    }
+
+    async fn ended(&self, track: &TrackHandle) {
+        let Some(queued) = queued_track(track).await else {
+            return;
+        };
+        if !self.data.settings.guild(self.guild_id).await.autoplay
+            || self.queue_continues(track).await
+        {
+            return;
+        }
+
+        // Nobody would hear it, autoplay would go on forever otherwise
+        let channel_id = match GuildPlayer::get(&self.ctx, self.guild_id).await {
+            Some(player) => player.channel().await.ok().flatten(),
+            None => None,
+        };
+        if channel_id.is_none_or(|channel_id| listeners(&self.ctx, self.guild_id, channel_id) == 0)
+        {
+            return;
+        }
+
+        // Looking up related tracks takes a while, the call's other events shouldn't wait for it
+        tokio::spawn(self.clone().queue_related(queued));
+    }
+}
+
+#[async_trait]
+impl EventHandler for Autoplay {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(track_events) = ctx {
+            for (state, track) in *track_events {
+                match state.playing {
+                    PlayMode::Play => {
+                        let url = queued_track(track)
+                            .await
+                            .and_then(|queued| queued.metadata.source_url);
+                        if let Some(url) = url {
+                            self.remember(&url).await;
+                        }
+                    }
+                    // Only when the queue ran out by itself, not after stopping or skipping
+                    PlayMode::End => self.ended(track).await,
+                    _ => {}
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,173 @@
+use crate::llm::{self, ReplyTarget};
+use crate::player::GuildPlayer;
+use crate::tts::normalize::{NormalizeOptions, cache_resolver, normalize};
+use crate::tts::{self, voices};
+use crate::{Context, Error};
+use poise::{CreateReply, serenity_prelude as serenity};
+use serenity::all::{CreateAttachment, GetMessages};
+use tracing::warn;
+
+/// Asks the bot a question
+#[poise::command(slash_command)]
+pub async fn ask(
+    ctx: Context<'_>,
+    #[description = "What do you want to know?"] prompt: String,
+    #[description = "Only show the answer to you."] private: Option<bool>,
+    #[description = "Speak the answer in your voice channel."] tts: Option<bool>,
+    #[description = "Include the last messages of this channel."] channel_context: Option<bool>,
+) -> Result<(), Error> {
+    answer(
+        ctx,
+        prompt,
+        private.unwrap_or(false),
+        tts,
+        channel_context.unwrap_or(false),
+    )
+    .await
+}
+
+/// Chats with the bot, taking the recent messages of this channel into account
+#[poise::command(slash_command)]
+pub async fn chat(
+    ctx: Context<'_>,
+    #[description = "Your message."] message: String,
+    #[description = "Only show the answer to you."] private: Option<bool>,
+    #[description = "Speak the answer in your voice channel."] tts: Option<bool>,
+) -> Result<(), Error> {
+    answer(ctx, message, private.unwrap_or(false), tts, true).await
+}
+
+/// Cancels your questions that are still waiting or being answered
+#[poise::command(slash_command, prefix_command)]
+pub async fn cancel(ctx: Context<'_>) -> Result<(), Error> {
+    let cancelled = ctx.data().llm_scheduler.cancel_user(ctx.author().id);
+
+    let reply = if cancelled == 0 {
+        "You have no questions to cancel."
+    } else {
+        "Cancelled your question."
+    };
+    ctx.send(CreateReply::default().content(reply).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+async fn answer(
+    ctx: Context<'_>,
+    prompt: String,
+    private: bool,
+    tts: Option<bool>,
+    channel_context: bool,
+) -> Result<(), Error> {
+    if private {
+        ctx.defer_ephemeral().await?;
+    } else {
+        ctx.defer().await?;
+    }
+
+    let reply = ctx
+        .send(CreateReply::default().content("-").ephemeral(private))
+        .await?;
+
+    let history = if channel_context {
+        let mut messages = ctx
+            .channel_id()
+            .messages(ctx.http(), GetMessages::default().limit(10))
+            .await?;
+        messages.reverse();
+        messages
+    } else {
+        Vec::new()
+    };
+
+    let persona = &llm::DEFAULT_PERSONA;
+    let request = llm::build_request(persona, history, ctx.author().id, &prompt);
+
+    let response = llm::chat(
+        ctx.serenity_context(),
+        ctx.data(),
+        &mut ReplyTarget::Command(ctx, &reply),
+        ctx.guild_id(),
+        ctx.author().id,
+        request,
+    )
+    .await?;
+
+    if tts == Some(false) || response.trim().is_empty() {
+        return Ok(());
+    }
+
+    // Without an explicit choice, the persona decides, but only if the bot is already listening
+    let handler_lock = match ctx.guild_id() {
+        Some(guild_id) if tts == Some(true) => GuildPlayer::join(
+            ctx.serenity_context(),
+            ctx.data(),
+            guild_id,
+            ctx.author().id,
+        )
+        .await
+        .ok()
+        .map(|player| player.call()),
+        Some(guild_id) if persona.speak_in_voice => {
+            GuildPlayer::shared_with(ctx.serenity_context(), guild_id, ctx.author().id)
+                .await
+                .map(|player| player.call())
+        }
+        _ => None,
+    };
+
+    if handler_lock.is_none() && tts.is_none() {
+        return Ok(());
+    }
+
+    let voice = voices::resolve(&ctx.data().settings, ctx.guild_id(), ctx.author().id).await;
+    let options = NormalizeOptions::for_voice(&ctx.data().settings, ctx.guild_id(), &voice).await;
+    let text = normalize(
+        &response,
+        &options,
+        cache_resolver(ctx.serenity_context(), ctx.guild_id()),
+    );
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let tts::Speech { input, audio } = match tts::synthesize(&text, &voice).await {
+        Ok(speech) => speech,
+        Err(e) => {
+            warn!("TTS synthesis failed: {}", e);
+            return Err(Error::Tts("Failed to generate TTS"));
+        }
+    };
+
+    match (ctx.guild_id(), handler_lock) {
+        (Some(guild_id), Some(handler_lock)) => {
+            if let Err(e) = ctx
+                .data()
+                .speech_queue
+                .play(guild_id, handler_lock, input)
+                .await
+            {
+                warn!("Failed to speak LLM answer: {}", e);
+            }
+        }
+        // Not in voice, hand out the audio file instead
+        _ => {
+            drop(input);
+            let wav = match audio.into_wav().await {
+                Ok(wav) => wav,
+                Err(e) => {
+                    warn!("TTS synthesis failed: {}", e);
+                    return Err(Error::Tts("Failed to generate TTS"));
+                }
+            };
+            ctx.send(
+                CreateReply::default()
+                    .attachment(CreateAttachment::bytes(wav, "tts.wav"))
+                    .ephemeral(private),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
+use crate::stt::listener::ListenMode;
+use crate::stt::whisper;
+use crate::{Context, Error};
+use poise::command;
+
+/// Talks with you out loud in voice
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("start", "stop"),
+    subcommand_required
+)]
+pub async fn converse(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Starts a conversation, I answer whenever you pause
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn start(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id().unwrap();
+    let serenity_ctx = ctx.serenity_context();
+
+    GuildPlayer::ensure_connected(ctx).await?;
+    let player = GuildPlayer::require_same_channel(ctx).await?;
+
+    if let Err(e) = whisper::get().await {
+        println!("Failed to load speech recognition: {}", e);
+        return ui::send_warning(ctx, "Speech recognition is not available.", "").await;
+    }
+
+    if let Err(e) = ctx
+        .data()
+        .listener
+        .start(
+            serenity_ctx,
+            ctx.data(),
+            guild_id,
+            ctx.channel_id(),
+            player.call(),
+            ListenMode::Converse(ctx.author().id),
+        )
+        .await
+    {
+        return ui::send_warning(
+            ctx,
+            e,
+            "End it with `/listen stop` or `/converse stop` first.",
+        )
+        .await;
+    }
+
+    ui::send_success(ctx, ":speaking_head: Conversation started", &format!(
+                    "Talk to me in voice, <@{}>. I answer whenever you pause, talk while I answer to interrupt me.\n\
+                    Only your voice is transcribed, the transcript is posted here. \
+                    Use `/converse stop` to end the conversation.",
+                    ctx.author().id
+                )).await?;
+    Ok(())
+}
+
+/// Ends the conversation
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let call = GuildPlayer::get(ctx.serenity_context(), guild_id)
+        .await
+        .map(|player| player.call());
+    if !ctx.data().listener.stop(ctx.data(), guild_id, call).await {
+        return ui::send_warning(ctx, "No conversation running.", "").await;
+    }
+
+    ui::send_success(ctx, ":wave: Conversation ended", "").await?;
+    Ok(())
+}
@@ -0,0 +1,93 @@
+use crate::commands::ui;
+use crate::commands::utils::to_time;
+use crate::llm::lifecycle::{MODEL_PATH, resident_memory};
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+
+/// Manages the language model
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    subcommands("load", "unload", "status"),
+    subcommand_required
+)]
+pub async fn llm(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Loads the language model in the background
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn load(ctx: Context<'_>) -> Result<(), Error> {
+    let title = if ctx.data().llm.load() {
+        ":hourglass: Loading model..."
+    } else {
+        ":warning: Model is not unloaded."
+    };
+
+    ui::send_success(ctx, title, &format!("State: {}", ctx.data().llm.state())).await?;
+    Ok(())
+}
+
+/// Unloads the language model once the running requests have finished
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn unload(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.data().llm.request_unload();
+    let status = ctx.data().llm.status();
+
+    let description = if status.active > 0 {
+        format!(
+            "Unloading after {} running request(s) finished.",
+            status.active
+        )
+    } else {
+        "Unloading now.".to_string()
+    };
+
+    ui::send_success(ctx, ":wastebasket: Unloading model", &description).await?;
+    Ok(())
+}
+
+/// Shows the state of the language model
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
+    let status = ctx.data().llm.status();
+
+    let mut fields = vec![
+        ("State", status.state.to_string(), true),
+        ("Active requests", status.active.to_string(), true),
+        (
+            "Queued questions",
+            ctx.data().llm_scheduler.pending().to_string(),
+            true,
+        ),
+        (
+            "Memory (RSS)",
+            resident_memory().unwrap_or_else(|| "Unknown".to_string()),
+            true,
+        ),
+        ("Idle for", to_time(status.idle_for.as_secs()), true),
+        ("Idle timeout", to_time(status.idle_timeout.as_secs()), true),
+        (
+            "Unload requested",
+            if status.unload_requested { "Yes" } else { "No" }.to_string(),
+            true,
+        ),
+    ];
+    if let Some(error) = status.last_error {
+        fields.push(("Last error", error, false));
+    }
+
+    ctx.send(
+        CreateReply::default().embed(
+            ui::theme(ctx)
+                .await
+                .success(":robot: Language model")
+                .description(format!("`{}`", MODEL_PATH))
+                .fields(fields),
+        ),
+    )
+    .await?;
+    Ok(())
+}
@@ -1,4 +1,12 @@
+pub mod ask;
+pub mod converse;
 pub mod help;
+pub mod llm;
 pub mod music;
 pub mod restart;
+pub mod theme;
+pub mod trigger;
+pub mod tts;
+pub mod ui;
 pub mod utils;
+pub mod voice;
@@ -0,0 +1,36 @@
+use crate::commands::ui;
+use crate::{Context, Error};
+use poise::command;
+
+/// Posts a "Now playing" card whenever the next song starts
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn announce(
+    ctx: Context<'_>,
+    #[description = "Whether to announce songs in the channel they were requested in."]
+    enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| guild.announce_tracks = enabled)
+        .await
+    {
+        println!("Failed to save announcements: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let description = if enabled {
+        "Each song is announced in the channel it was requested in."
+    } else {
+        "Songs are no longer announced."
+    };
+    ui::send_success(ctx, ":loudspeaker: Announcements updated", description).await?;
+    Ok(())
+}
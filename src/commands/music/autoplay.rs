@@ -0,0 +1,50 @@
+use crate::commands::ui;
+use crate::{Context, Error};
+use poise::command;
+
+/// Plays related songs when the queue runs out
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("on", "off"),
+    subcommand_required
+)]
+pub async fn autoplay(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+async fn set_autoplay(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| guild.autoplay = enabled)
+        .await
+    {
+        println!("Failed to save autoplay: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+    Ok(())
+}
+
+/// Plays a related song whenever the last song of the queue ends
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn on(ctx: Context<'_>) -> Result<(), Error> {
+    set_autoplay(ctx, true).await?;
+    ui::send_success(
+        ctx,
+        ":infinity: Autoplay on",
+        "When the queue runs out I keep playing related songs.",
+    )
+    .await
+}
+
+/// Stops when the last song of the queue ends
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn off(ctx: Context<'_>) -> Result<(), Error> {
+    set_autoplay(ctx, false).await?;
+    ui::send_success(ctx, "Autoplay off", "I stop when the queue runs out.").await
+}
@@ -1,42 +1,14 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
 use crate::{Context, Error};
-use poise::{CreateReply, command};
-use serenity::builder::CreateEmbed;
-use serenity::model::prelude::*;
+use poise::command;
 
 #[command(prefix_command, slash_command, guild_only)]
 pub async fn clear(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let guild_id = ctx.guild_id().unwrap();
-
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        queue.stop();
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title("Cleared the queue")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    } else {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Not in a voice channel.")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    }
+    let queue = GuildPlayer::require_controller(ctx).await?.queue().await?;
+    queue.stop();
+    ui::send_success(ctx, "Cleared the queue", "").await?;
     Ok(())
 }
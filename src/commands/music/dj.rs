@@ -0,0 +1,40 @@
+use crate::commands::ui;
+use crate::{Context, Error};
+use poise::command;
+use serenity::model::prelude::*;
+
+/// Sets the role that may control the music from outside my voice channel
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn dj(
+    ctx: Context<'_>,
+    #[description = "DJ role, leave empty to only allow admins."] role: Option<Role>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let role_id = role.as_ref().map(|role| role.id);
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| guild.dj_role = role_id)
+        .await
+    {
+        println!("Failed to save DJ role: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let description = match role_id {
+        Some(role_id) => format!(
+            "<@&{}> may skip, pause and stop the music without being in my voice channel.",
+            role_id
+        ),
+        None => "Only admins may control the music from outside my voice channel.".to_string(),
+    };
+
+    ui::send_success(ctx, ":headphones: DJ role updated", &description).await?;
+    Ok(())
+}
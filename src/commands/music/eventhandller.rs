@@ -1,12 +1,160 @@
+use crate::Data;
+use crate::commands::music::play::get_ytdlp_args;
+use crate::commands::ui::Theme;
+use crate::player::{GuildPlayer, queued_track};
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, CreateMessage, GuildId};
 use serenity::async_trait;
+use songbird::events::context_data::DisconnectKind;
+use songbird::input::YoutubeDl;
+use songbird::tracks::{PlayMode, TrackHandle, TrackState};
 use songbird::{Event, EventContext, EventHandler};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tracing::{info, warn};
 
-pub struct CustomSongbirdEventHandler;
+/// A failing track is restarted this often before it is skipped.
+const MAX_TRACK_RETRIES: u32 = 3;
+/// Rejoin attempts after the voice connection dropped, each waits longer than the last.
+const MAX_REJOINS: u32 = 3;
+const REJOIN_DELAY: Duration = Duration::from_secs(5);
+
+/// Recovers a call from failing streams and dropped voice connections.
+#[derive(Clone)]
+pub struct CustomSongbirdEventHandler {
+    ctx: serenity::Context,
+    data: Data,
+    guild_id: GuildId,
+    /// Counts successful (re)connects, a rejoin is not needed if this changed in the meantime.
+    connects: Arc<AtomicUsize>,
+}
 
 impl CustomSongbirdEventHandler {
-    pub fn new() -> Self {
-        Self
+    pub fn new(ctx: serenity::Context, data: Data, guild_id: GuildId) -> Self {
+        Self {
+            ctx,
+            data,
+            guild_id,
+            connects: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    async fn notice(&self, channel_id: ChannelId, title: &str, description: &str) {
+        let embed = Theme::of(&self.data.settings, Some(self.guild_id))
+            .await
+            .warning(title)
+            .description(description);
+        if let Err(e) = channel_id
+            .send_message(&self.ctx.http, CreateMessage::new().embed(embed))
+            .await
+        {
+            println!("Failed to post voice notice: {}", e);
+        }
+    }
+
+    /// Restarts a track whose stream failed from where it stopped, with freshly resolved stream URLs.
+    async fn restart_track(&self, state: &TrackState, handle: &TrackHandle) {
+        let Some(mut queued) = queued_track(handle).await else {
+            return;
+        };
+        let title = queued
+            .metadata
+            .title
+            .clone()
+            .unwrap_or_else(|| "Unknown Title".to_string());
+
+        let url = match &queued.metadata.source_url {
+            Some(url) if queued.retries < MAX_TRACK_RETRIES => url.clone(),
+            _ => {
+                self.notice(
+                    queued.channel_id,
+                    "Skipped a track.",
+                    &format!("**{}** kept failing to play.", title),
+                )
+                .await;
+                return;
+            }
+        };
+        let Some(player) = GuildPlayer::get(&self.ctx, self.guild_id).await else {
+            return;
+        };
+
+        // Live streams can't be resumed at a position
+        let position = queued.metadata.duration.map(|_| state.position);
+        queued.retries += 1;
+        info!(
+            "Restarting {} at {:?}, attempt {}",
+            title, position, queued.retries
+        );
+
+        let source = YoutubeDl::new(self.data.http_client.clone(), url).user_args(get_ytdlp_args());
+        if let Err(e) = player
+            .restart(source.into(), queued, handle, position)
+            .await
+        {
+            warn!("Failed to restart track: {}", e);
+        }
+    }
+
+    /// Rejoins after the voice connection dropped, unless the bot left or got connected again.
+    async fn rejoin(self, channel_id: Option<ChannelId>, connects: usize) {
+        for attempt in 1..=MAX_REJOINS {
+            tokio::time::sleep(REJOIN_DELAY * attempt).await;
+
+            let Some(player) = GuildPlayer::get(&self.ctx, self.guild_id).await else {
+                return;
+            };
+            if self.connects.load(Ordering::Relaxed) != connects {
+                return;
+            }
+            let Some(channel_id) = channel_id else {
+                break;
+            };
+
+            match player.move_to(&self.ctx, channel_id).await {
+                Ok(()) => {
+                    info!("Rejoined voice channel after {} attempt(s)", attempt);
+                    return;
+                }
+                Err(e) => warn!("Failed to rejoin voice channel: {}", e),
+            }
+        }
+
+        let Some(player) = GuildPlayer::get(&self.ctx, self.guild_id).await else {
+            return;
+        };
+        // Told where the music was requested
+        let current = match player.queue().await {
+            Ok(queue) => queue.current(),
+            Err(_) => None,
+        };
+        let notice_channel = match current {
+            Some(track) => queued_track(&track).await.map(|queued| queued.channel_id),
+            None => None,
+        };
+
+        if let Err(e) = player.leave(&self.ctx, &self.data).await {
+            warn!("Failed to leave after losing the voice connection: {}", e);
+        }
+        if let Some(channel_id) = notice_channel {
+            self.notice(
+                channel_id,
+                "Lost the voice connection.",
+                "I couldn't reconnect to the voice channel, use `/play` to bring me back.",
+            )
+            .await;
+        }
+    }
+
+    /// Leaves for good after being disconnected on purpose, so the next `/play` joins again.
+    async fn leave(self) {
+        let Some(player) = GuildPlayer::get(&self.ctx, self.guild_id).await else {
+            return;
+        };
+        if let Err(e) = player.leave(&self.ctx, &self.data).await {
+            warn!("Failed to leave after being disconnected: {}", e);
+        }
     }
 }
 
@@ -16,38 +164,46 @@ impl EventHandler for CustomSongbirdEventHandler {
         match ctx {
             EventContext::Track(track_events) => {
                 for (state, handle) in *track_events {
-                    match state.playing {
-                        songbird::tracks::PlayMode::Play => {
-                            info!("Track started: {:?}", handle.uuid());
-                        }
-                        songbird::tracks::PlayMode::End => {
-                            info!(
-                                "Track finished after {:.1}s (UUID: {:?})",
-                                state.play_time.as_secs_f64(),
-                                handle.uuid()
-                            );
-                        }
-                        songbird::tracks::PlayMode::Pause => {
-                            info!("Track paused: {:?}", handle.uuid());
-                        }
-                        songbird::tracks::PlayMode::Stop => {
-                            info!("Track stopped: {:?}", handle.uuid());
-                        }
-                        songbird::tracks::PlayMode::Errored(ref error) => {
-                            warn!("Track errored: {:?} (UUID: {:?})", error, handle.uuid());
-                        }
-                        _ => {}
+                    if let PlayMode::Errored(ref error) = state.playing {
+                        warn!("Track errored: {:?} (UUID: {:?})", error, handle.uuid());
+                        self.restart_track(state, handle).await;
                     }
                 }
             }
             EventContext::DriverConnect(_) => {
                 info!("Voice driver connected");
+                self.connects.fetch_add(1, Ordering::Relaxed);
             }
             EventContext::DriverReconnect(_) => {
-                warn!("Voice driver reconnecting...");
+                info!("Voice driver reconnected");
+                self.connects.fetch_add(1, Ordering::Relaxed);
             }
-            EventContext::DriverDisconnect(_) => {
-                info!("Voice driver disconnected");
+            EventContext::DriverDisconnect(disconnect) => {
+                info!(
+                    "Voice driver disconnected: {:?} ({:?})",
+                    disconnect.kind, disconnect.reason
+                );
+                // Connect failures are reported by whoever tried to join
+                if !matches!(
+                    disconnect.kind,
+                    DisconnectKind::Reconnect | DisconnectKind::Runtime
+                ) {
+                    // Out of the channel for good, end what was still running in it
+                    self.data.recorder.stop(self.guild_id);
+                    self.data
+                        .listener
+                        .stop(&self.data, self.guild_id, None)
+                        .await;
+                } else if disconnect.reason.is_none() {
+                    // Deliberate, e.g. a moderator kicked the bot from the channel
+                    tokio::spawn(self.clone().leave());
+                } else {
+                    let channel_id = disconnect
+                        .channel_id
+                        .map(|channel| ChannelId::new(channel.0.get()));
+                    let connects = self.connects.load(Ordering::Relaxed);
+                    tokio::spawn(self.clone().rejoin(channel_id, connects));
+                }
             }
             _ => {}
         }
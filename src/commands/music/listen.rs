@@ -0,0 +1,80 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
+use crate::stt::listener::{ListenMode, wake_word};
+use crate::stt::whisper;
+use crate::{Context, Error};
+use poise::command;
+
+/// Listens for voice commands in the voice channel
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("start", "stop"),
+    subcommand_required
+)]
+pub async fn listen(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Starts listening for voice commands in your voice channel
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn start(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id().unwrap();
+    let serenity_ctx = ctx.serenity_context();
+
+    GuildPlayer::ensure_connected(ctx).await?;
+    let player = GuildPlayer::require_same_channel(ctx).await?;
+
+    // Loading takes a moment, better now than on the first command
+    if let Err(e) = whisper::get().await {
+        println!("Failed to load speech recognition: {}", e);
+        return ui::send_warning(ctx, "Speech recognition is not available.", "").await;
+    }
+
+    if let Err(e) = ctx
+        .data()
+        .listener
+        .start(
+            serenity_ctx,
+            ctx.data(),
+            guild_id,
+            ctx.channel_id(),
+            player.call(),
+            ListenMode::Commands,
+        )
+        .await
+    {
+        return ui::send_warning(ctx, e, "").await;
+    }
+
+    ui::send_success(
+        ctx,
+        ":ear: Listening for voice commands",
+        &format!(
+            "Say **{}** followed by `play <song>`, `skip`, `pause` or `resume`.\n\
+                    Everyone in the voice channel is transcribed locally, nothing is stored. \
+                    Use `/listen stop` to stop.",
+            wake_word()
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Stops listening for voice commands
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let call = GuildPlayer::get(ctx.serenity_context(), guild_id)
+        .await
+        .map(|player| player.call());
+    if !ctx.data().listener.stop(ctx.data(), guild_id, call).await {
+        return ui::send_warning(ctx, "Not listening.", "").await;
+    }
+
+    ui::send_success(ctx, ":mute: Stopped listening", "").await?;
+    Ok(())
+}
@@ -1,12 +1,19 @@
+pub mod announce;
+pub mod autoplay;
 pub mod clear;
+pub mod dj;
 pub mod eventhandller;
 pub mod join;
+pub mod listen;
+pub mod move_here;
 pub mod nowplaying;
 pub mod pause;
 pub mod play;
 pub mod queue;
+pub mod record;
 pub mod resume;
 pub mod say;
 pub mod shuffle;
 pub mod skip;
+pub mod sound;
 pub mod stop;
@@ -0,0 +1,52 @@
+use crate::commands::ui;
+use crate::player::{self, GuildPlayer};
+use crate::{Context, Error};
+use poise::command;
+
+/// Moves me and the queue to your voice channel
+#[command(slash_command, prefix_command, guild_only, rename = "move-here")]
+pub async fn move_here(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let player = GuildPlayer::connected(ctx).await?;
+    let serenity_ctx = ctx.serenity_context();
+
+    let Some(channel_id) = player::user_channel(serenity_ctx, player.guild_id, ctx.author().id)
+    else {
+        return Err(Error::Voice("Join a voice channel first!"));
+    };
+
+    let bot_channel = player.channel().await?;
+    if bot_channel == Some(channel_id) {
+        ui::send_success(ctx, "Already in your voice channel!", "").await?;
+        return Ok(());
+    }
+
+    // Only DJs and admins may take the music away from others
+    if let Some(bot_channel) = bot_channel {
+        let allowed = player::listeners(serenity_ctx, player.guild_id, bot_channel) == 0
+            || player::is_dj(
+                serenity_ctx,
+                ctx.data(),
+                player.guild_id,
+                ctx.author().id,
+                bot_channel,
+            )
+            .await;
+        if !allowed {
+            return Err(Error::Voice(
+                "Others are still listening in my voice channel, only DJs and admins can move me.",
+            ));
+        }
+    }
+
+    player.move_to(serenity_ctx, channel_id).await?;
+
+    ui::send_success(
+        ctx,
+        ":arrow_right: Moved",
+        &format!("The queue keeps playing in <#{}>.", channel_id),
+    )
+    .await?;
+    Ok(())
+}
@@ -1,69 +1,42 @@
+use crate::commands::ui;
 use crate::commands::utils::to_time;
+use crate::player::{GuildPlayer, track_metadata};
 use crate::{Context, Error};
 use poise::{CreateReply, command};
-use serenity::builder::CreateEmbed;
-use serenity::model::prelude::*;
 
 /// Shows the currently playing track
 #[command(prefix_command, slash_command, guild_only, aliases("np"))]
 pub async fn nowplaying(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let guild_id = ctx.guild_id().unwrap();
+    let queue = GuildPlayer::connected(ctx).await?.queue().await?;
 
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-
-        let current = match queue.current() {
-            Some(current) => current,
-            None => {
-                ctx.send(
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Nothing is playing right now.")
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
+    let current = match queue.current() {
+        Some(current) => current,
+        None => {
+            ui::send_nothing_playing(ctx).await?;
 
-                return Ok(());
-            }
-        };
+            return Ok(());
+        }
+    };
 
-        let track_info = current.get_info().await.unwrap();
-
-        // Simplified version without metadata
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title("Now Playing")
-                    .description("Track information is limited in this version.")
-                    .fields(vec![
-                        ("Position", to_time(track_info.position.as_secs()), true),
-                        ("Status", format!("{:?}", track_info.playing), true),
-                    ])
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    } else {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Not in a voice channel.")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    }
+    let track_info = current
+        .get_info()
+        .await
+        .map_err(|_| Error::Voice("Failed to get the state of the current track."))?;
+    let metadata = track_metadata(&current).await.unwrap_or_default();
+
+    ctx.send(
+        CreateReply::default().embed(
+            ui::theme(ctx)
+                .await
+                .track_card("Now Playing", &metadata)
+                .fields(vec![
+                    ("Position", to_time(track_info.position.as_secs()), true),
+                    ("Status", format!("{:?}", track_info.playing), true),
+                ]),
+        ),
+    )
+    .await?;
     Ok(())
 }
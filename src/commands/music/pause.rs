@@ -1,55 +1,19 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
 use crate::{Context, Error};
-use poise::{CreateReply, command};
-use serenity::builder::CreateEmbed;
-use serenity::model::prelude::*;
+use poise::command;
 /// Pauses the currently playing track
 #[command(prefix_command, slash_command, guild_only)]
 pub async fn pause(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let guild_id = ctx.guild_id().unwrap();
-
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        if let Err(e) = queue.pause() {
-            println!("Failed to pause track: {}", e);
-            ctx.send(
-                CreateReply::default().embed(
-                    CreateEmbed::new()
-                        .colour(0xf38ba8)
-                        .title("Failed to pause track.")
-                        .timestamp(Timestamp::now()),
-                ),
-            )
-            .await?;
-            return Ok(());
-        }
-
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title(":pause_button: Paused!")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    } else {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Not in a voice channel.")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
+    let queue = GuildPlayer::require_controller(ctx).await?.queue().await?;
+    if let Err(e) = queue.pause() {
+        println!("Failed to pause track: {}", e);
+        ui::send_warning(ctx, "Failed to pause track.", "").await?;
+        return Ok(());
     }
+
+    ui::send_success(ctx, ":pause_button: Paused!", "").await?;
     Ok(())
 }
@@ -1,17 +1,14 @@
-use crate::commands::music::eventhandller::CustomSongbirdEventHandler;
+use crate::commands::ui;
+use crate::player::GuildPlayer;
 use crate::{Context, Error};
 use poise::{CreateReply, ReplyHandle, serenity_prelude as serenity};
 use regex::Regex;
 use reqwest::Client;
-use serenity::all::CreateEmbed;
-use serenity::model::prelude::*;
 use songbird::input::{AuxMetadata, Compose, YoutubeDl};
-use songbird::{Call, CoreEvent};
 use tokio::process::Command;
-use tokio::sync::MutexGuard;
 use tracing::info;
 
-fn get_ytdlp_args() -> Vec<String> {
+pub fn get_ytdlp_args() -> Vec<String> {
     let args = vec![
         // Use the standard web client. bgutil-ytdlp-pot-provider supplies PO tokens
         // automatically via the YT_DLP_POT_BGUTIL_BASEURL env var, so this is now
@@ -34,6 +31,29 @@ fn get_ytdlp_args() -> Vec<String> {
     args
 }
 
+/// The video urls of a playlist, mix or `ytsearch` query, without resolving each video.
+pub async fn flat_playlist(target: &str) -> std::io::Result<Vec<String>> {
+    let output = Command::new("yt-dlp")
+        .args([
+            "-j",
+            "--flat-playlist",
+            "--extractor-args",
+            "youtube:player_client=tv,mweb",
+            target,
+        ])
+        .output()
+        .await?;
+    let raw_list = String::from_utf8_lossy(&output.stdout);
+
+    let re =
+        Regex::new(r#""url": "(https://www\.youtube\.com/watch\?v=[A-Za-z0-9_-]{11})""#).unwrap();
+
+    Ok(re
+        .captures_iter(&raw_list)
+        .map(|cap| cap[1].to_string())
+        .collect())
+}
+
 #[poise::command(slash_command, prefix_command, guild_only)]
 pub async fn play(
     ctx: Context<'_>,
@@ -41,90 +61,49 @@ pub async fn play(
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
     // Auto-join the user's voice channel if the bot isn't already in one
-    if manager.get(ctx.guild_id().unwrap()).is_none() {
-        let channel_id = ctx
-            .guild()
-            .unwrap()
-            .voice_states
-            .get(&ctx.author().id)
-            .and_then(|voice_state| voice_state.channel_id);
-
-        let connect_to = match channel_id {
-            Some(channel) => channel,
-            None => {
-                ctx.send(
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Join a voice channel first!")
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-                return Ok(());
-            }
-        };
-
-        if let Err(_) = manager.join(ctx.guild_id().unwrap(), connect_to).await {
-            ctx.send(
-                CreateReply::default().embed(
-                    CreateEmbed::new()
-                        .colour(0xf38ba8)
-                        .title(":warning: Error joining channel.")
-                        .description("Please ensure I have the correct permissions.")
-                        .timestamp(Timestamp::now()),
-                ),
-            )
-            .await?;
-            return Ok(());
-        }
-    }
+    let player = GuildPlayer::join_to_enqueue(
+        ctx.serenity_context(),
+        ctx.data(),
+        ctx.guild_id().unwrap(),
+        ctx.author().id,
+    )
+    .await?;
 
     let reply = ctx
         .send(
             CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title(":notes: Fetching song(s)...")
-                    .description("Please wait...")
-                    .timestamp(Timestamp::now()),
+                ui::theme(ctx)
+                    .await
+                    .success(":notes: Fetching song(s)...")
+                    .description("Please wait..."),
             ),
         )
         .await?;
 
-    if let Some(handler_lock) = manager.get(ctx.guild_id().unwrap()) {
-        let http_client = &ctx.data().http_client;
-        let mut handler = handler_lock.lock().await;
-
-        let _ = handler.deafen(true).await;
+    let http_client = &ctx.data().http_client;
 
-        handler.add_global_event(
-            songbird::Event::Core(CoreEvent::DriverDisconnect),
-            CustomSongbirdEventHandler::new(),
-        );
+    // Redirect YouTube Music links to regular YouTube
+    let url = if input.starts_with("http") && input.contains("music.") {
+        input.replace("music.", "")
+    } else {
+        input.clone()
+    };
 
-        // Redirect YouTube Music links to regular YouTube
-        let url = if input.starts_with("http") && input.contains("music.") {
-            input.replace("music.", "")
-        } else {
-            input.clone()
-        };
+    let result = if !url.starts_with("http") {
+        handle_search(ctx, url, &reply, http_client, &player).await
+    } else if url.contains("playlist") {
+        handle_playlist(ctx, url, &reply, http_client, &player).await
+    } else if url.contains("live") {
+        handle_livestream(ctx, url, &reply, http_client, &player).await
+    } else {
+        handle_direct_url(ctx, url, &reply, http_client, &player).await
+    };
 
-        if !url.starts_with("http") {
-            handle_search(ctx, url, &reply, http_client, &mut handler).await?;
-        } else if url.contains("playlist") {
-            handle_playlist(ctx, url, &reply, http_client, &mut handler).await?;
-        } else if url.contains("live") {
-            handle_livestream(ctx, url, &reply, http_client, &mut handler).await?;
-        } else {
-            handle_direct_url(ctx, url, &reply, http_client, handler).await?;
-        }
+    // The error is reported in place of the "Fetching" message
+    if let Err(e) = result {
+        let _ = reply.delete(ctx).await;
+        return Err(e);
     }
 
     Ok(())
@@ -135,7 +114,7 @@ async fn handle_direct_url(
     url: String,
     reply: &ReplyHandle<'_>,
     http_client: &Client,
-    mut handler: MutexGuard<'_, Call>,
+    player: &GuildPlayer,
 ) -> Result<(), Error> {
     let mut source = YoutubeDl::new(http_client.clone(), url.clone()).user_args(get_ytdlp_args());
 
@@ -143,58 +122,24 @@ async fn handle_direct_url(
         Ok(meta) => meta,
         Err(e) => {
             info!("Failed to fetch metadata for URL {}: {:?}", url, e);
-            reply
-                .edit(
-                    ctx,
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Failed to fetch video!")
-                            .description(format!(
-                                "Could not access this video. It may be private, region-locked, or temporarily unavailable.\n\nError: {}",
-                                e
-                            ))
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-            return Ok(());
+            return Err(Error::Source(format!(
+                "Could not access this video. It may be private, region-locked, or temporarily unavailable.\n\nError: {}",
+                e
+            )));
         }
     };
 
-    handler.enqueue(source.into()).await;
+    player
+        .enqueue(source.into(), metadata.clone(), ctx.channel_id())
+        .await?;
 
     reply
         .edit(
             ctx,
             CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title(":notes: Added to queue!")
-                    .thumbnail(metadata.thumbnail.clone().unwrap_or_else(|| {
-                        "https://images.unsplash.com/photo-1611162616475-46b635cb6868".to_string()
-                    }))
-                    .description(format!(
-                        "{} - {}\n{}",
-                        metadata
-                            .title
-                            .clone()
-                            .unwrap_or_else(|| "Unknown Title".to_string()),
-                        metadata
-                            .artist
-                            .clone()
-                            .unwrap_or_else(|| "Unknown Artist".to_string()),
-                        if let Some(duration) = &metadata.duration {
-                            format!(
-                                "Duration: {}:{:02}",
-                                duration.as_secs() / 60,
-                                duration.as_secs() % 60
-                            )
-                        } else {
-                            "Duration: Unknown".to_string()
-                        }
-                    ))
-                    .timestamp(Timestamp::now()),
+                ui::theme(ctx)
+                    .await
+                    .track_card(":notes: Added to queue!", &metadata),
             ),
         )
         .await?;
@@ -207,7 +152,7 @@ async fn handle_livestream(
     url: String,
     reply: &ReplyHandle<'_>,
     http_client: &Client,
-    handler: &mut MutexGuard<'_, Call>,
+    player: &GuildPlayer,
 ) -> Result<(), Error> {
     let mut source = YoutubeDl::new(http_client.clone(), url.clone()).user_args(get_ytdlp_args());
 
@@ -218,50 +163,25 @@ async fn handle_livestream(
                 "Failed to fetch livestream metadata for URL {}: {:?}",
                 url, e
             );
-            reply
-                .edit(
-                    ctx,
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Failed to access livestream!")
-                            .description(format!(
-                                "Could not access this stream. It may be offline, private, or region-locked.\n\nError: {}",
-                                e
-                            ))
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-            return Ok(());
+            return Err(Error::Source(format!(
+                "Could not access this stream. It may be offline, private, or region-locked.\n\nError: {}",
+                e
+            )));
         }
     };
 
-    handler.enqueue(source.into()).await;
+    player
+        .enqueue(source.into(), metadata.clone(), ctx.channel_id())
+        .await?;
 
     reply
         .edit(
             ctx,
             CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title(":notes: Added to queue!")
-                    .thumbnail(metadata.thumbnail.clone().unwrap_or_else(|| {
-                        "https://images.unsplash.com/photo-1611162616475-46b635cb6868".to_string()
-                    }))
-                    .description(format!(
-                        "{} - {}",
-                        metadata
-                            .title
-                            .clone()
-                            .unwrap_or_else(|| "Unknown Title".to_string()),
-                        metadata
-                            .artist
-                            .clone()
-                            .unwrap_or_else(|| "Unknown Artist".to_string()),
-                    ))
-                    .fields(vec![("Total playtime", "Live stream", true)])
-                    .timestamp(Timestamp::now()),
+                ui::theme(ctx)
+                    .await
+                    .track_card(":notes: Added to queue!", &metadata)
+                    .field("Duration", "Live stream", true),
             ),
         )
         .await?;
@@ -274,62 +194,25 @@ async fn handle_playlist(
     url: String,
     reply: &ReplyHandle<'_>,
     http_client: &Client,
-    handler: &mut MutexGuard<'_, Call>,
+    player: &GuildPlayer,
 ) -> Result<(), Error> {
-    let get_raw_list = Command::new("yt-dlp")
-        .args([
-            "-j",
-            "--flat-playlist",
-            "--extractor-args",
-            "youtube:player_client=tv,mweb",
-            &url,
-        ])
-        .output()
-        .await;
-
-    let raw_list = match get_raw_list {
-        Ok(output) => String::from_utf8_lossy(&output.stdout).into_owned(),
+    let urls = match flat_playlist(&url).await {
+        Ok(urls) => urls,
         Err(e) => {
             info!("Failed to fetch playlist: {:?}", e);
-            reply
-                .edit(
-                    ctx,
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Failed to fetch playlist!")
-                            .description("Could not retrieve the playlist. Please check the URL and try again.")
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-            return Ok(());
+            return Err(Error::Source(
+                "Could not retrieve the playlist. Please check the URL and try again.".to_string(),
+            ));
         }
     };
 
-    let re =
-        Regex::new(r#""url": "(https://www\.youtube\.com/watch\?v=[A-Za-z0-9_-]{11})""#).unwrap();
-
-    let urls: Vec<String> = re
-        .captures_iter(&raw_list)
-        .map(|cap| cap[1].to_string())
-        .collect();
-
     if urls.is_empty() {
-        reply
-            .edit(
-                ctx,
-                CreateReply::default().embed(
-                    CreateEmbed::new()
-                        .colour(0xf38ba8)
-                        .title(":warning: No tracks found in playlist!")
-                        .timestamp(Timestamp::now()),
-                ),
-            )
-            .await?;
-        return Ok(());
+        return Err(Error::Source(
+            "No tracks found in the playlist.".to_string(),
+        ));
     }
 
+    let theme = ui::theme(ctx).await;
     let mut queued: Vec<(String, String, bool)> = Vec::new();
 
     for track_url in &urls {
@@ -338,18 +221,27 @@ async fn handle_playlist(
             YoutubeDl::new(http_client.clone(), track_url.clone()).user_args(get_ytdlp_args());
 
         // Best-effort metadata; don't abort the whole playlist on a single failure
-        let (title, artist) = match source.aux_metadata().await {
-            Ok(meta) => (
-                meta.title.unwrap_or_else(|| "<Unknown>".to_string()),
-                meta.artist.unwrap_or_else(|| "<Unknown>".to_string()),
-            ),
+        let mut metadata = match source.aux_metadata().await {
+            Ok(meta) => meta,
             Err(e) => {
                 info!("Could not fetch metadata for {}: {:?}", track_url, e);
-                ("<Unknown>".to_string(), "<Unknown>".to_string())
+                AuxMetadata::default()
             }
         };
-
-        handler.enqueue(source.into()).await;
+        // Needed to restart the track if its stream fails
+        metadata.source_url.get_or_insert_with(|| track_url.clone());
+        let title = metadata
+            .title
+            .clone()
+            .unwrap_or_else(|| "<Unknown>".to_string());
+        let artist = metadata
+            .artist
+            .clone()
+            .unwrap_or_else(|| "<Unknown>".to_string());
+
+        player
+            .enqueue(source.into(), metadata, ctx.channel_id())
+            .await?;
 
         queued.push((title, format!("{} - [Link]({})", artist, track_url), false));
 
@@ -357,10 +249,9 @@ async fn handle_playlist(
             .edit(
                 ctx,
                 CreateReply::default().embed(
-                    CreateEmbed::new()
-                        .title(":page_facing_up: Queueing playlist:")
-                        .fields(queued.clone())
-                        .timestamp(Timestamp::now()),
+                    theme
+                        .success(":page_facing_up: Queueing playlist:")
+                        .fields(queued.clone()),
                 ),
             )
             .await?;
@@ -374,7 +265,7 @@ async fn handle_search(
     search: String,
     reply: &ReplyHandle<'_>,
     http_client: &Client,
-    handler: &mut MutexGuard<'_, Call>,
+    player: &GuildPlayer,
 ) -> Result<(), Error> {
     let mut source =
         YoutubeDl::new_search(http_client.clone(), search.clone()).user_args(get_ytdlp_args());
@@ -383,59 +274,22 @@ async fn handle_search(
         Ok(meta) => meta,
         Err(e) => {
             info!("Search failed for '{}': {:?}", search, e);
-            reply
-                .edit(
-                    ctx,
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Search failed!")
-                            .description(format!(
-                                "Could not find a result for **{}**.\n\nError: {}",
-                                search, e
-                            ))
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-            return Ok(());
+            return Err(Error::Source(format!(
+                "Could not find a result for **{}**.\n\nError: {}",
+                search, e
+            )));
         }
     };
 
-    handler.enqueue(source.into()).await;
-
-    reply
-        .edit(
-            ctx,
-            create_search_result_embed(metadata, handler.queue().len()),
-        )
+    let queued = player
+        .enqueue(source.into(), metadata.clone(), ctx.channel_id())
         .await?;
 
-    Ok(())
-}
+    let card = ui::theme(ctx)
+        .await
+        .track_card(":notes: Song added to the queue!", &metadata)
+        .field("Songs queued", queued.to_string(), true);
+    reply.edit(ctx, CreateReply::default().embed(card)).await?;
 
-fn create_search_result_embed(metadata: AuxMetadata, queue_length: usize) -> CreateReply {
-    CreateReply::default()
-        .embed(
-            CreateEmbed::new()
-                .colour(0xffffff)
-                .title(":notes: Song added to the queue!")
-                .thumbnail(metadata.thumbnail.clone().unwrap_or_else(|| {
-                    "https://images.unsplash.com/photo-1611162616475-46b635cb6868".to_string()
-                }))
-                .description(format!(
-                    "{} - {}",
-                    metadata
-                        .title
-                        .clone()
-                        .unwrap_or_else(|| "Unknown Title".to_string()),
-                    metadata
-                        .artist
-                        .clone()
-                        .unwrap_or_else(|| "Unknown Artist".to_string()),
-                ))
-                .fields(vec![("Songs queued", format!("{}", queue_length), true)])
-                .timestamp(Timestamp::now()),
-        )
-        .ephemeral(false)
+    Ok(())
 }
@@ -1,76 +1,35 @@
-use crate::commands::utils::to_time;
+use crate::commands::ui;
+use crate::player::{GuildPlayer, track_metadata};
 use crate::{Context, Error};
 use poise::{CreateReply, command};
-use serenity::builder::CreateEmbed;
-use serenity::model::prelude::*;
 
 /// Shows the current queue
 #[command(prefix_command, slash_command, guild_only)]
 pub async fn queue(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let guild_id = ctx.guild_id().unwrap();
+    let queue = GuildPlayer::connected(ctx).await?.queue().await?;
 
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-
-        let _ = match queue.current() {
-            Some(current) => current,
-            None => {
-                ctx.send(
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Nothing is playing right now.")
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-
-                return Ok(());
-            }
-        };
-
-        let mut desc = String::from("+ - + - + - + - + - + - + - + - + - +\n");
-        let mut total_time = 0;
+    let tracks = queue.current_queue();
+    if tracks.is_empty() {
+        ui::send_nothing_playing(ctx).await?;
+        return Ok(());
+    }
 
-        // Simplified version that doesn't rely on metadata
-        for (i, _song) in queue.current_queue().iter().enumerate() {
-            desc.push_str(&format!("{}. Track {}\n", i + 1, i + 1));
-            // We can't reliably get metadata, so we'll just use a placeholder duration
-            total_time += 180; // Assume 3 minutes per song
-        }
+    let mut lines = Vec::with_capacity(tracks.len());
+    let mut total_time = 0;
+    for (i, track) in tracks.iter().enumerate() {
+        let metadata = track_metadata(track).await.unwrap_or_default();
+        // Assume 3 minutes for tracks without a known duration
+        total_time += metadata.duration.map_or(180, |duration| duration.as_secs());
+        lines.push(format!(
+            "{}. {}",
+            i + 1,
+            metadata.title.as_deref().unwrap_or("Unknown Title")
+        ));
+    }
 
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title(":notes: - Queue - :notes:")
-                    .fields(vec![
-                        ("Queue length", format!("{}", queue.len()), true),
-                        ("Total time", to_time(total_time), true),
-                    ])
-                    .description(desc)
-                    .timestamp(Timestamp::now()),
-            ),
-        )
+    ctx.send(CreateReply::default().embed(ui::theme(ctx).await.queue_page(&lines, total_time)))
         .await?;
-    } else {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Not in a voice channel.")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    }
     Ok(())
 }
@@ -0,0 +1,133 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
+use crate::recording::MAX_CLIP_SECS;
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+use serenity::all::CreateAttachment;
+
+/// Records the voice channel
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("start", "stop"),
+    subcommand_required
+)]
+pub async fn record(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Starts recording your voice channel
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn start(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id().unwrap();
+    let serenity_ctx = ctx.serenity_context();
+
+    if ctx.data().recorder.is_recording(guild_id) {
+        return ui::send_warning(ctx, "Already recording.", "Use `/record stop` first.").await;
+    }
+
+    GuildPlayer::ensure_connected(ctx).await?;
+    let player = GuildPlayer::require_same_channel(ctx).await?;
+
+    // Everyone in the channel gets told before anything is recorded
+    let theme = ui::theme(ctx).await;
+    let notice = ctx
+        .send(
+            CreateReply::default().embed(
+                theme
+                    .success(":red_circle: Recording the voice channel")
+                    .colour(theme.warning)
+                    .description(format!(
+                        "<@{}> started a recording. Everyone speaking in the voice channel is recorded \
+                        and the files are posted here when it ends.\n\
+                        Leave the channel if you don't want to be recorded. \
+                        Anyone can end it with `/record stop`, `/clip` posts up to the last {} seconds.",
+                        ctx.author().id,
+                        MAX_CLIP_SECS
+                    )),
+            ),
+        )
+        .await?;
+    let notice_id = notice.message().await?.id;
+
+    if let Err(e) = ctx
+        .data()
+        .recorder
+        .start(
+            serenity_ctx,
+            ctx.data(),
+            guild_id,
+            ctx.channel_id(),
+            notice_id,
+            player.call(),
+        )
+        .await
+    {
+        notice
+            .edit(ctx, CreateReply::default().embed(theme.warning(e)))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Stops the recording and posts the files
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn stop(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if !ctx.data().recorder.stop(guild_id) {
+        return ui::send_warning(ctx, "Not recording.", "").await;
+    }
+
+    ui::send_success(
+        ctx,
+        ":stop_button: Recording stopped",
+        "The files are being uploaded.",
+    )
+    .await?;
+    Ok(())
+}
+
+/// Posts the last seconds of the running recording
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn clip(
+    ctx: Context<'_>,
+    #[description = "How many seconds to clip, 30 by default"]
+    #[min = 1]
+    #[max = 60]
+    seconds: Option<u32>,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id().unwrap();
+    let seconds = seconds.unwrap_or(30).clamp(1, MAX_CLIP_SECS as u32);
+
+    let ogg = match ctx.data().recorder.clip(guild_id, seconds as usize) {
+        Some(Ok(ogg)) => ogg,
+        Some(Err(e)) => {
+            println!("Failed to encode clip: {}", e);
+            return ui::send_warning(ctx, "Failed to create the clip.", "").await;
+        }
+        None => {
+            return ui::send_warning(
+                ctx,
+                "Not recording.",
+                "Start a recording with `/record start`.",
+            )
+            .await;
+        }
+    };
+
+    ctx.send(
+        CreateReply::default()
+            .embed(
+                ui::theme(ctx)
+                    .await
+                    .success(format!(":scissors: The last {} seconds", seconds)),
+            )
+            .attachment(CreateAttachment::bytes(ogg, "clip.ogg")),
+    )
+    .await?;
+    Ok(())
+}
@@ -1,45 +1,17 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
 use crate::{Context, Error};
-use poise::{CreateReply, command};
-use serenity::builder::CreateEmbed;
-use serenity::model::prelude::*;
+use poise::command;
 
 /// Resumes playback of the current track
 #[command(prefix_command, slash_command, guild_only)]
 pub async fn resume(ctx: Context<'_>) -> Result<(), Error> {
     ctx.defer().await?;
 
-    let guild_id = ctx.guild_id().unwrap();
+    let queue = GuildPlayer::require_controller(ctx).await?.queue().await?;
+    let _ = queue.resume();
 
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        let _ = queue.resume();
-
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xffffff)
-                    .title(":arrow_forward: Resumed!")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    } else {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Not in a voice channel.")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
-    }
+    ui::send_success(ctx, ":arrow_forward: Resumed!", "").await?;
     ctx.reply("Resumed.").await?;
     Ok(())
 }
@@ -1,310 +1,191 @@
+use crate::commands::ui;
+use crate::player::{self, GuildPlayer};
+use crate::tts::markup::{self, Prosody, Segment, SpeechOptions};
+use crate::tts::normalize::{NormalizeOptions, cache_resolver, normalize};
+use crate::tts::{self, voices};
 use crate::{Context, Error};
 use ::serenity::all::CreateAttachment;
-use piper_rs::synth::AudioOutputConfig;
-use poise::{CreateReply, command, serenity_prelude as serenity};
-use rand::Rng;
-use serenity::builder::CreateEmbed;
-use serenity::model::prelude::*;
-use songbird::input::File as SongbirdFile;
-use songbird::{Event, EventContext, EventHandler, TrackEvent};
-use std::env;
-use std::path::{Path, PathBuf};
-use std::sync::{Arc, LazyLock};
-use tokio::fs;
-use tokio::sync::Mutex;
-use tokio::task;
+use poise::{CreateReply, command};
+use std::time::Duration;
 use tracing::{info, warn};
 
-const DEFAULT_TTS_CONFIG_PATH: &str = "de_DE-lars.onnx.json";
-const MAX_TTS_LENGTH: usize = usize::MAX;
+pub const MAX_TTS_LENGTH: usize = 400;
 
-static TTS_SYNTHEZISER: LazyLock<Arc<Mutex<Option<piper_rs::synth::PiperSpeechSynthesizer>>>> =
-    LazyLock::new(|| Arc::new(Mutex::new(None)));
-
-fn is_emoji(ch: char) -> bool {
-    let code = ch as u32;
-    matches!(
-        code,
-        0x1F300..=0x1FAFF
-            | 0x1F1E6..=0x1F1FF
-            | 0x2600..=0x26FF
-            | 0x2700..=0x27BF
-            | 0xFE00..=0xFE0F
-    )
-}
-
-fn filter_emojis(input: &str) -> String {
-    input.chars().filter(|&ch| !is_emoji(ch)).collect()
-}
-
-#[derive(Clone)]
-struct ResumeAndCleanup {
-    handler_lock: Arc<Mutex<songbird::Call>>,
-    resume: bool,
-    file_path: PathBuf,
+pub async fn autocomplete_voice<'a>(
+    _ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let partial = partial.to_lowercase();
+    voices::available()
+        .await
+        .into_iter()
+        .filter(move |voice| voice.to_lowercase().contains(&partial))
+        .take(25)
 }
 
-#[serenity::async_trait]
-impl EventHandler for ResumeAndCleanup {
-    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if matches!(ctx, EventContext::Track(_)) {
-            if self.resume {
-                let handler = self.handler_lock.lock().await;
-                let queue = handler.queue();
-                let _ = queue.resume();
-            }
-
-            if let Err(e) = fs::remove_file(&self.file_path).await {
-                warn!("Failed to remove TTS temp file {:?}: {}", self.file_path, e);
-            }
-        }
-
-        None
-    }
-}
-pub async fn synthesize_audio(text: &str) -> Result<PathBuf, String> {
-    let config_path_raw = PathBuf::from(
-        env::var("TTS_CONFIG_PATH").unwrap_or_else(|_| DEFAULT_TTS_CONFIG_PATH.to_string()),
-    );
-    let config_path = if config_path_raw.is_relative() {
-        let models_dir = PathBuf::from("models");
-        if config_path_raw.starts_with(&models_dir) {
-            config_path_raw
-        } else {
-            models_dir.join(config_path_raw)
-        }
+/// Drops the text being spoken, the next queued text starts right away
+#[command(slash_command, prefix_command, guild_only, rename = "say-skip")]
+pub async fn say_skip(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let speech_queue = &ctx.data().speech_queue;
+
+    let embed = if speech_queue.skip(guild_id).await {
+        let pending = speech_queue.pending(guild_id).await;
+        ui::theme(ctx)
+            .await
+            .success(":fast_forward: Skipped speech")
+            .description(format!("{} more queued.", pending))
     } else {
-        config_path_raw
-    };
-
-    if fs::metadata(&config_path).await.is_err() {
-        return Err(format!(
-            "TTS model not found at `{}`. Place your model files in `./models` and set `TTS_CONFIG_PATH` if needed.",
-            config_path.display()
-        ));
-    }
-
-    let output_dir = PathBuf::from("models/tts_cache");
-    if let Err(e) = fs::create_dir_all(&output_dir).await {
-        return Err(format!(
-            "Could not create `models/tts_cache` directory: {}",
-            e
-        ));
-    }
-
-    let file_name = format!("astionic_tts_{}.wav", rand::rng().random::<u64>());
-    let output_path = output_dir.join(file_name);
-
-    let text_owned = text.to_string();
-    let config_path_buf = config_path.clone();
-    let output_path_clone = output_path.clone();
-    let speaker_id = env::var("TTS_SPEAKER_ID").ok();
-
-    if TTS_SYNTHEZISER.lock().await.is_none() {
-        let model = piper_rs::from_config_path(Path::new(&config_path_buf))
-            .map_err(|e| format!("Failed to load model: {}", e))?;
-
-        if let Some(sid) = speaker_id {
-            let sid = sid
-                .parse::<i64>()
-                .map_err(|_| "TTS_SPEAKER_ID must be a number".to_string())?;
-            model.set_speaker(sid);
-        }
-
-        let synth = piper_rs::synth::PiperSpeechSynthesizer::new(model)
-            .map_err(|e| format!("Failed to create synthesizer: {}", e))?;
-
-        *TTS_SYNTHEZISER.lock().await = Some(synth);
-    }
-
-    let output_config = AudioOutputConfig {
-        rate: Some(7u8),
-        volume: None,
-        pitch: None,
-        appended_silence_ms: None,
+        ui::theme(ctx).await.warning("Nothing is being spoken.")
     };
 
-    let synth_lock = &*TTS_SYNTHEZISER;
-    let synth_guard = synth_lock.lock().await;
-    let synth = synth_guard
-        .as_ref()
-        .ok_or("Speech synthesizer not initialized".to_string())?;
-
-    synth
-        .synthesize_to_file(
-            Path::new(&output_path_clone),
-            text_owned,
-            Some(output_config),
-        )
-        .map_err(|e| format!("Synthesis failed: {}", e))?;
-
-    Ok(output_path)
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
 }
 
 /// Generates local TTS audio and plays it in voice
+///
+/// The text may contain markup like `[pause 500ms]`, `[slow]...[/slow]`, `[loud]`, `[quiet]`,
+/// `[high]`, `[low]` and `[speaker 2]...[/speaker]`.
 #[command(slash_command, prefix_command, guild_only)]
 pub async fn say(
     ctx: Context<'_>,
     #[description = "Text to speak."] text: String,
+    #[description = "Voice to speak with, defaults to your /voice setting."]
+    #[autocomplete = "autocomplete_voice"]
+    voice: Option<String>,
+    #[description = "Speaking rate in percent."]
+    #[min = 0]
+    #[max = 100]
+    rate: Option<u8>,
+    #[description = "Pitch in percent."]
+    #[min = 0]
+    #[max = 100]
+    pitch: Option<u8>,
+    #[description = "Volume in percent."]
+    #[min = 0]
+    #[max = 100]
+    volume: Option<u8>,
+    #[description = "Pause between sentences in milliseconds."]
+    #[min = 0]
+    #[max = 10000]
+    pause: Option<u64>,
 ) -> Result<(), Error> {
     ctx.defer().await?;
 
+    let guild_id = ctx.guild_id().unwrap();
     let trimmed = text.trim();
-    let filtered = filter_emojis(trimmed);
-    let filtered_trimmed = filtered.trim();
 
-    if filtered_trimmed.is_empty() {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Please provide text to speak.")
-                    .timestamp(Timestamp::now()),
-            ),
-        )
-        .await?;
+    let voice = match voice {
+        Some(voice) => voice,
+        None => voices::resolve(&ctx.data().settings, Some(guild_id), ctx.author().id).await,
+    };
+
+    let options = SpeechOptions {
+        prosody: Prosody {
+            rate: rate.or(Prosody::default().rate),
+            pitch,
+            volume,
+        },
+        sentence_pause: Duration::from_millis(pause.unwrap_or(0)).min(markup::MAX_PAUSE),
+    };
+    let normalize_options =
+        NormalizeOptions::for_voice(&ctx.data().settings, Some(guild_id), &voice).await;
+
+    // Markup first, so tags like `[pause 500ms]` aren't read out as numbers
+    let segments: Vec<Segment> = markup::parse(trimmed, &options)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Speech {
+                text,
+                prosody,
+                speaker,
+            } => {
+                let text = normalize(
+                    &text,
+                    &normalize_options,
+                    cache_resolver(ctx.serenity_context(), Some(guild_id)),
+                );
+                (!text.is_empty()).then_some(Segment::Speech {
+                    text,
+                    prosody,
+                    speaker,
+                })
+            }
+            pause => Some(pause),
+        })
+        .collect();
+
+    if !segments
+        .iter()
+        .any(|segment| matches!(segment, Segment::Speech { .. }))
+    {
+        ui::send_warning(ctx, "Please provide text to speak.", "").await?;
         return Ok(());
     }
 
-    if filtered_trimmed.chars().count() > MAX_TTS_LENGTH {
-        ctx.send(
-            CreateReply::default().embed(
-                CreateEmbed::new()
-                    .colour(0xf38ba8)
-                    .title(":warning: Message too long.")
-                    .description(format!(
-                        "Please keep the message under {} characters.",
-                        MAX_TTS_LENGTH
-                    ))
-                    .timestamp(Timestamp::now()),
+    if trimmed.chars().count() > MAX_TTS_LENGTH {
+        ui::send_warning(
+            ctx,
+            "Message too long.",
+            &format!(
+                "Please keep the message under {} characters.",
+                MAX_TTS_LENGTH
             ),
         )
         .await?;
         return Ok(());
     }
 
-    println!("Generating TTS for text: {}", filtered_trimmed);
-
-    let manager = songbird::get(ctx.serenity_context())
-        .await
-        .expect("Songbird Voice client placed in at initialisation.")
-        .clone();
-
-    let guild_id = ctx.guild_id().unwrap();
-
-    let user_channel_id = ctx
-        .guild()
-        .unwrap()
-        .voice_states
-        .get(&ctx.author().id)
-        .and_then(|voice_state| voice_state.channel_id);
+    println!("Generating TTS for text: {}", trimmed);
 
-    if manager.get(guild_id).is_none() {
-        if let Some(connect_to) = user_channel_id {
-            if let Err(_) = manager.join(guild_id, connect_to).await {
-                ctx.send(
-                    CreateReply::default().embed(
-                        CreateEmbed::new()
-                            .colour(0xf38ba8)
-                            .title(":warning: Error joining channel.")
-                            .description("Please ensure I have the correct permissions.")
-                            .timestamp(Timestamp::now()),
-                    ),
-                )
-                .await?;
-                return Ok(());
-            }
-        }
-    }
-
-    let handler_lock = if user_channel_id.is_some() {
-        match manager.get(guild_id) {
-            Some(handler) => Some(handler),
-            None => return Ok(()),
-        }
-    } else {
-        None
+    // Without a voice channel the speech is only sent as a file
+    let player = match player::user_channel(ctx.serenity_context(), guild_id, ctx.author().id) {
+        Some(_) => Some(GuildPlayer::ensure_connected(ctx).await?),
+        None => None,
     };
 
-    let tts_result = synthesize_audio(filtered_trimmed).await;
-    let output_path = match tts_result {
-        Ok(path) => {
-            info!("TTS synthesis successful: {:?}", path);
-            path
-        }
+    let speech = match tts::synthesize_segments(segments, &voice).await {
+        Ok(speech) => speech,
         Err(e) => {
             warn!("TTS synthesis failed: {}", e);
-            ctx.send(
-                CreateReply::default().embed(
-                    CreateEmbed::new()
-                        .colour(0xf38ba8)
-                        .title(":warning: TTS synthesis failed.")
-                        .description(e)
-                        .timestamp(Timestamp::now()),
-                ),
-            )
-            .await?;
+            ui::send_warning(ctx, "TTS synthesis failed.", &e).await?;
             return Ok(());
         }
     };
 
-    if handler_lock.is_none() {
-        ctx.send(CreateReply::default().attachment(CreateAttachment::path(output_path).await?))
-            .await?;
-        return Ok(());
-    }
-
-    let handler_lock = handler_lock.expect("Voice handler missing");
-
-    let queue = {
-        let handler = handler_lock.lock().await;
-        handler.queue().clone()
-    };
-
-    let mut was_playing = false;
-    if let Some(current) = queue.current() {
-        if let Ok(info) = current.get_info().await {
-            if matches!(info.playing, songbird::tracks::PlayMode::Play) {
-                was_playing = true;
+    let tts::Speech {
+        input: speech_input,
+        audio,
+    } = speech;
+
+    match player {
+        // Start speaking right away, the rest of the text is synthesized while the first sentence plays
+        Some(player) => {
+            if let Err(e) = ctx
+                .data()
+                .speech_queue
+                .play(guild_id, player.call(), speech_input)
+                .await
+            {
+                ui::send_warning(ctx, e, "").await?;
+                return Ok(());
             }
+            info!("TTS queued for playback");
         }
+        None => drop(speech_input),
     }
 
-    if was_playing {
-        if let Err(e) = queue.pause() {
-            warn!("Failed to pause current track: {}", e);
-            ctx.send(
-                CreateReply::default().embed(
-                    CreateEmbed::new()
-                        .colour(0xf38ba8)
-                        .title(":warning: Failed to pause music.")
-                        .timestamp(Timestamp::now()),
-                ),
-            )
-            .await?;
-            return Ok(());
+    match audio.into_wav().await {
+        Ok(wav) => {
+            ctx.send(CreateReply::default().attachment(CreateAttachment::bytes(wav, "tts.wav")))
+                .await?;
+        }
+        Err(e) => {
+            warn!("TTS synthesis failed: {}", e);
+            ui::send_warning(ctx, "TTS synthesis failed.", &e).await?;
         }
     }
 
-    let tts_source = SongbirdFile::new(output_path.clone());
-    let tts_handle = {
-        let mut handler = handler_lock.lock().await;
-        handler.play_input(tts_source.into())
-    };
-
-    let cleanup_handler = ResumeAndCleanup {
-        handler_lock: handler_lock.clone(),
-        resume: was_playing,
-        file_path: output_path.clone(),
-    };
-
-    let _ = tts_handle.add_event(Event::Track(TrackEvent::End), cleanup_handler.clone());
-    let _ = tts_handle.add_event(Event::Track(TrackEvent::Error), cleanup_handler);
-
-    info!("TTS playback started: {:?}", output_path);
-
-    ctx.send(CreateReply::default().attachment(CreateAttachment::path(output_path).await?))
-        .await?;
-
     Ok(())
 }
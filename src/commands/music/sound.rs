@@ -0,0 +1,223 @@
+use crate::commands::ui;
+use crate::player::GuildPlayer;
+use crate::soundboard::{self, MAX_SOUND_DURATION, MAX_SOUND_SIZE, MAX_SOUNDS_PER_GUILD};
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+use serenity::model::prelude::*;
+use songbird::input::File;
+
+async fn autocomplete_sound<'a>(
+    ctx: Context<'_>,
+    partial: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    let sounds = match ctx.guild_id() {
+        Some(guild_id) => soundboard::list(guild_id).await,
+        None => Vec::new(),
+    };
+    let partial = partial.to_lowercase();
+    sounds
+        .into_iter()
+        .filter(move |sound| sound.contains(&partial))
+        .take(25)
+}
+
+/// Plays short sound clips in voice
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("add", "play", "list", "remove"),
+    subcommand_required
+)]
+pub async fn sound(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Adds a sound to the soundboard
+#[command(slash_command, guild_only, required_permissions = "MANAGE_GUILD")]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Name to play the sound with."] name: String,
+    #[description = "Audio file (mp3, wav, ogg or flac)."] file: Attachment,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id().unwrap();
+
+    let name = match soundboard::validate_name(&name) {
+        Ok(name) => name,
+        Err(e) => return ui::send_warning(ctx, "Invalid name.", &e).await,
+    };
+
+    let extension = file
+        .filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .filter(|extension| soundboard::EXTENSIONS.contains(&extension.as_str()));
+    let Some(extension) = extension else {
+        return ui::send_warning(
+            ctx,
+            "Unsupported file.",
+            &format!("Supported formats: {}", soundboard::EXTENSIONS.join(", ")),
+        )
+        .await;
+    };
+
+    if file.size as u64 > MAX_SOUND_SIZE {
+        return ui::send_warning(
+            ctx,
+            "File too large.",
+            &format!("Sounds may be at most {} KiB.", MAX_SOUND_SIZE / 1024),
+        )
+        .await;
+    }
+
+    let sounds = soundboard::list(guild_id).await;
+    if sounds.contains(&name) {
+        return ui::send_warning(
+            ctx,
+            "Sound already exists.",
+            "Remove it first with `/sound remove`.",
+        )
+        .await;
+    }
+    if sounds.len() >= MAX_SOUNDS_PER_GUILD {
+        return ui::send_warning(
+            ctx,
+            "Soundboard full.",
+            &format!("This server already has {} sounds.", MAX_SOUNDS_PER_GUILD),
+        )
+        .await;
+    }
+
+    let audio = file.download().await?;
+    let duration = match soundboard::probe_duration(audio.clone(), &extension).await {
+        Ok(duration) => duration,
+        Err(e) => return ui::send_warning(ctx, "Could not read the audio.", &e).await,
+    };
+    if duration > MAX_SOUND_DURATION {
+        return ui::send_warning(
+            ctx,
+            "Sound too long.",
+            &format!(
+                "Sounds may be at most {} seconds long.",
+                MAX_SOUND_DURATION.as_secs()
+            ),
+        )
+        .await;
+    }
+
+    if let Err(e) = soundboard::save(guild_id, &name, &extension, &audio).await {
+        println!("Failed to save sound: {}", e);
+        return Err(Error::Config("Failed to save sound"));
+    }
+
+    ui::send_success(
+        ctx,
+        format!(":loud_sound: Added `{}`", name),
+        &format!("{:.1} seconds", duration.as_secs_f64()),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Plays a sound in your voice channel
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn play(
+    ctx: Context<'_>,
+    #[description = "Sound to play."]
+    #[autocomplete = "autocomplete_sound"]
+    name: String,
+) -> Result<(), Error> {
+    ctx.defer().await?;
+    let guild_id = ctx.guild_id().unwrap();
+
+    let Some(path) = soundboard::find(guild_id, &name.trim().to_lowercase()).await else {
+        return ui::send_warning(
+            ctx,
+            "Unknown sound.",
+            "Use `/sound list` to see all sounds.",
+        )
+        .await;
+    };
+
+    let player = GuildPlayer::ensure_connected(ctx).await?;
+
+    // Queued with the speech, so it plays over the music the same way
+    if let Err(e) = ctx
+        .data()
+        .speech_queue
+        .play(guild_id, player.call(), File::new(path).into())
+        .await
+    {
+        return ui::send_warning(ctx, e, "").await;
+    }
+
+    ui::send_success(ctx, format!(":loud_sound: Playing `{}`", name), "").await?;
+    Ok(())
+}
+
+/// Lists all sounds of the server
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let sounds = soundboard::list(ctx.guild_id().unwrap()).await;
+
+    let description = if sounds.is_empty() {
+        "No sounds yet, add one with `/sound add`.".to_string()
+    } else {
+        sounds
+            .iter()
+            .map(|sound| format!("`{}`", sound))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    ctx.send(
+        CreateReply::default().embed(
+            ui::theme(ctx)
+                .await
+                .success(format!(
+                    ":loud_sound: Soundboard ({}/{})",
+                    sounds.len(),
+                    MAX_SOUNDS_PER_GUILD
+                ))
+                .description(description),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Removes a sound from the soundboard
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Sound to remove."]
+    #[autocomplete = "autocomplete_sound"]
+    name: String,
+) -> Result<(), Error> {
+    let name = name.trim().to_lowercase();
+
+    match soundboard::remove(ctx.guild_id().unwrap(), &name).await {
+        Ok(true) => {}
+        Ok(false) => {
+            return ui::send_warning(
+                ctx,
+                "Unknown sound.",
+                "Use `/sound list` to see all sounds.",
+            )
+            .await;
+        }
+        Err(e) => {
+            println!("Failed to remove sound: {}", e);
+            return Err(Error::Config("Failed to remove sound"));
+        }
+    }
+
+    ui::send_success(ctx, format!(":wastebasket: Removed `{}`", name), "").await?;
+    Ok(())
+}
@@ -7,7 +7,7 @@ pub async fn restart(ctx: Context<'_>) -> Result<(), Error> {
     let data = ctx.data();
 
     let reply = ctx.reply("Attempting restart").await?;
-    let message = reply.into_message().await.unwrap();
+    let message = reply.into_message().await?;
     let reply_id = message.id;
 
     let channel_id = message.channel_id;
@@ -16,7 +16,7 @@ pub async fn restart(ctx: Context<'_>) -> Result<(), Error> {
         "restart_signal.txt",
         format!("{}\n{}", channel_id, reply_id),
     )
-    .expect("Failed to write restart signal file.");
+    .map_err(|_| Error::Config("Failed to write restart signal file."))?;
 
     data.restart_requested.cancel();
 
@@ -0,0 +1,100 @@
+use crate::commands::ui::{self, DEFAULT_THEME};
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+
+/// Parses colours like `#a6e3a1` or `a6e3a1`.
+fn parse_colour(colour: &str) -> Option<u32> {
+    let hex = colour.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// Changes the colours of my replies in this server
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("set", "reset"),
+    subcommand_required
+)]
+pub async fn theme(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Sets the colours of my replies
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Colour of normal replies, like #a6e3a1."] accent: Option<String>,
+    #[description = "Colour of warnings and errors, like #f38ba8."] warning: Option<String>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let parse = |colour: Option<String>| match colour {
+        Some(colour) => parse_colour(&colour).map(Some).ok_or(colour),
+        None => Ok(None),
+    };
+    let (accent, warning) = match (parse(accent), parse(warning)) {
+        (Ok(accent), Ok(warning)) => (accent, warning),
+        (Err(colour), _) | (_, Err(colour)) => {
+            return ui::send_warning(
+                ctx,
+                "Invalid colour.",
+                &format!("`{}` is not a colour like `#a6e3a1`.", colour),
+            )
+            .await;
+        }
+    };
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| {
+            if accent.is_some() {
+                guild.accent_colour = accent;
+            }
+            if warning.is_some() {
+                guild.warning_colour = warning;
+            }
+        })
+        .await
+    {
+        println!("Failed to save theme: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let theme = ui::theme(ctx).await;
+    ctx.send(
+        CreateReply::default()
+            .embed(theme.success(":art: Theme updated"))
+            .embed(theme.warning("Warnings look like this.")),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Resets the colours of my replies
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn reset(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| {
+            guild.accent_colour = None;
+            guild.warning_colour = None;
+        })
+        .await
+    {
+        println!("Failed to save theme: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    ctx.send(CreateReply::default().embed(DEFAULT_THEME.success(":art: Theme reset")))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use crate::commands::ui;
+use crate::triggers::{
+    self, ActionKind, DEFAULT_COOLDOWN, MAX_TRIGGERS_PER_GUILD, PatternKind, TriggerRule,
+};
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+use serenity::model::prelude::*;
+
+const MAX_VALUE_LENGTH: usize = 400;
+
+/// Checks that the action can be run with `value`, returns the value to store.
+async fn validate_action(
+    guild_id: GuildId,
+    action: ActionKind,
+    value: &str,
+) -> Result<String, String> {
+    let value = value.trim();
+    if value.is_empty() || value.chars().count() > MAX_VALUE_LENGTH {
+        return Err(format!(
+            "Values must be between 1 and {} characters long.",
+            MAX_VALUE_LENGTH
+        ));
+    }
+
+    match action {
+        ActionKind::Reply | ActionKind::Speak => Ok(value.to_string()),
+        ActionKind::Sound => {
+            let name = value.to_lowercase();
+            match triggers::sound_path(guild_id, &name).await {
+                Some(_) => Ok(name),
+                None => Err(format!(
+                    "Unknown sound. Use `/sound list` to see all sounds, `{}` is always available.",
+                    triggers::BUILTIN_SOUND
+                )),
+            }
+        }
+        ActionKind::React => match triggers::parse_emoji(value) {
+            Some(_) => Ok(value.to_string()),
+            None => {
+                Err("Use a single emoji, e.g. 👍 or a custom emoji of this server.".to_string())
+            }
+        },
+    }
+}
+
+fn describe(rule: &TriggerRule) -> String {
+    let pattern = match rule.kind {
+        PatternKind::Keyword => format!("keyword `{}`", rule.pattern),
+        PatternKind::Regex => format!("regex `{}`", rule.pattern),
+    };
+    let action = match rule.action {
+        ActionKind::Reply => format!("reply \"{}\"", rule.value),
+        ActionKind::Sound => format!("sound `{}`", rule.value),
+        ActionKind::React => format!("react {}", rule.value),
+        ActionKind::Speak => format!("speak \"{}\"", rule.value),
+    };
+    format!(
+        "**#{}** {} → {} ({}s cooldown)",
+        rule.id, pattern, action, rule.cooldown_secs
+    )
+}
+
+/// Manages rules that answer matching messages
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommands("add", "list", "remove"),
+    subcommand_required
+)]
+pub async fn trigger(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Adds a rule answering messages that match a keyword or regex
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn add(
+    ctx: Context<'_>,
+    #[description = "Match a keyword or a regular expression."] kind: PatternKind,
+    #[description = "Keyword or regular expression, case is ignored."] pattern: String,
+    #[description = "What to do when a message matches."] action: ActionKind,
+    #[description = "Reply text, sound name, emoji or text to speak."] value: String,
+    #[description = "Seconds before the rule fires again, 30 by default."]
+    #[min = 0]
+    #[max = 86400]
+    cooldown: Option<u64>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Err(e) = triggers::compile(kind, &pattern) {
+        return ui::send_warning(ctx, "Invalid pattern.", &e).await;
+    }
+    let value = match validate_action(guild_id, action, &value).await {
+        Ok(value) => value,
+        Err(e) => return ui::send_warning(ctx, "Invalid value.", &e).await,
+    };
+
+    let mut rule = TriggerRule {
+        id: 0,
+        kind,
+        pattern: pattern.trim().to_string(),
+        action,
+        value,
+        cooldown_secs: cooldown.unwrap_or(DEFAULT_COOLDOWN.as_secs()),
+    };
+
+    let mut full = false;
+    let result = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| {
+            if guild.triggers.len() >= MAX_TRIGGERS_PER_GUILD {
+                full = true;
+                return;
+            }
+            rule.id = guild.triggers.iter().map(|rule| rule.id).max().unwrap_or(0) + 1;
+            guild.triggers.push(rule.clone());
+        })
+        .await;
+    if let Err(e) = result {
+        println!("Failed to save trigger: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+    if full {
+        return ui::send_warning(
+            ctx,
+            "Too many triggers.",
+            &format!(
+                "This server already has {} triggers, remove one first.",
+                MAX_TRIGGERS_PER_GUILD
+            ),
+        )
+        .await;
+    }
+    ctx.data().triggers.invalidate(guild_id, None);
+
+    ui::send_success(ctx, ":zap: Trigger added", &describe(&rule)).await?;
+    Ok(())
+}
+
+/// Lists the rules of the server
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let rules = ctx
+        .data()
+        .settings
+        .guild(ctx.guild_id().unwrap())
+        .await
+        .triggers;
+
+    let description = if rules.is_empty() {
+        "No triggers yet, add one with `/trigger add`.".to_string()
+    } else {
+        rules.iter().map(describe).collect::<Vec<_>>().join("\n")
+    };
+
+    ctx.send(
+        CreateReply::default().embed(
+            ui::theme(ctx)
+                .await
+                .success(format!(
+                    ":zap: Triggers ({}/{})",
+                    rules.len(),
+                    MAX_TRIGGERS_PER_GUILD
+                ))
+                .description(description),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Removes a rule
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn remove(
+    ctx: Context<'_>,
+    #[description = "Number of the trigger, see `/trigger list`."] id: u32,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    let mut removed = None;
+    let result = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| {
+            if let Some(index) = guild.triggers.iter().position(|rule| rule.id == id) {
+                removed = Some(guild.triggers.remove(index));
+            }
+        })
+        .await;
+    if let Err(e) = result {
+        println!("Failed to remove trigger: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let Some(rule) = removed else {
+        return ui::send_warning(
+            ctx,
+            "Unknown trigger.",
+            "Use `/trigger list` to see all triggers.",
+        )
+        .await;
+    };
+    ctx.data().triggers.invalidate(guild_id, Some(rule.id));
+
+    ui::send_success(ctx, ":wastebasket: Trigger removed", &describe(&rule)).await?;
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use crate::commands::ui;
+use crate::commands::utils::to_size;
+use crate::tts::cache;
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+use serenity::model::prelude::*;
+use tokio::task;
+
+/// Manages text to speech
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    subcommands("channel", "cache"),
+    subcommand_required
+)]
+pub async fn tts(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Sets the channel whose messages are read aloud in voice
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn channel(
+    ctx: Context<'_>,
+    #[description = "Channel to read aloud, leave empty to turn it off."]
+    #[channel_types("Text", "Voice")]
+    channel: Option<GuildChannel>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let channel_id = channel.as_ref().map(|channel| channel.id);
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| guild.tts_channel = channel_id)
+        .await
+    {
+        println!("Failed to save TTS channel: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let description = match channel_id {
+        Some(channel_id) => format!(
+            "Messages in <#{}> are read aloud to everyone in my voice channel. Use `/voice autoread` to opt out.",
+            channel_id
+        ),
+        None => "Messages are no longer read aloud.".to_string(),
+    };
+
+    ui::send_success(ctx, ":speaking_head: TTS channel updated", &description).await?;
+    Ok(())
+}
+
+/// Manages the cache of synthesized speech, it is shared by all servers
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    owners_only,
+    subcommands("stats", "purge"),
+    subcommand_required
+)]
+pub async fn cache(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Shows how much speech is cached
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn stats(ctx: Context<'_>) -> Result<(), Error> {
+    let stats = match task::spawn_blocking(cache::stats).await {
+        Ok(Ok(stats)) => stats,
+        Ok(Err(e)) => {
+            println!("Failed to read TTS cache: {}", e);
+            return Err(Error::Tts("Failed to read TTS cache"));
+        }
+        Err(_) => return Err(Error::Tts("Failed to read TTS cache")),
+    };
+
+    let requests = stats.hits + stats.misses;
+    let hit_rate = if requests > 0 {
+        format!("{:.0}%", stats.hits as f64 / requests as f64 * 100.0)
+    } else {
+        "-".to_string()
+    };
+    let limit = if stats.max_bytes > 0 {
+        to_size(stats.max_bytes)
+    } else {
+        "Disabled".to_string()
+    };
+
+    ctx.send(
+        CreateReply::default().embed(
+            ui::theme(ctx)
+                .await
+                .success(":floppy_disk: TTS cache")
+                .fields(vec![
+                    ("Files", stats.files.to_string(), true),
+                    ("Size", to_size(stats.bytes), true),
+                    ("Limit", limit, true),
+                    ("Hits", stats.hits.to_string(), true),
+                    ("Misses", stats.misses.to_string(), true),
+                    ("Hit rate", hit_rate, true),
+                ]),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Deletes all cached speech
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn purge(ctx: Context<'_>) -> Result<(), Error> {
+    let deleted = match task::spawn_blocking(cache::purge).await {
+        Ok(Ok(deleted)) => deleted,
+        Ok(Err(e)) => {
+            println!("Failed to purge TTS cache: {}", e);
+            return Err(Error::Tts("Failed to purge TTS cache"));
+        }
+        Err(_) => return Err(Error::Tts("Failed to purge TTS cache")),
+    };
+
+    ui::send_success(
+        ctx,
+        ":wastebasket: TTS cache purged",
+        &format!("Deleted {} file(s).", deleted),
+    )
+    .await?;
+    Ok(())
+}
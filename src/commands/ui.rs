@@ -0,0 +1,127 @@
+//! Replies of the commands. Every embed is built from the guild's theme here, so they look the
+//! same everywhere and a guild can change their colour with `/theme`.
+
+use crate::commands::utils::to_time;
+use crate::error::BotError;
+use crate::settings::SettingsStore;
+use crate::{Context, Error};
+use poise::CreateReply;
+use serenity::all::{CreateEmbed, GuildId, Timestamp};
+use songbird::input::AuxMetadata;
+
+/// Shown for tracks without a thumbnail.
+pub const DEFAULT_THUMBNAIL: &str = "https://images.unsplash.com/photo-1611162616475-46b635cb6868";
+
+#[derive(Clone, Copy)]
+pub struct Theme {
+    /// Colour of successful replies.
+    pub accent: u32,
+    /// Colour of warnings and errors.
+    pub warning: u32,
+}
+
+pub const DEFAULT_THEME: Theme = Theme {
+    accent: 0xffffff,
+    warning: 0xf38ba8,
+};
+
+impl Theme {
+    pub async fn of(settings: &SettingsStore, guild_id: Option<GuildId>) -> Self {
+        let Some(guild_id) = guild_id else {
+            return DEFAULT_THEME;
+        };
+        let settings = settings.guild(guild_id).await;
+        Self {
+            accent: settings.accent_colour.unwrap_or(DEFAULT_THEME.accent),
+            warning: settings.warning_colour.unwrap_or(DEFAULT_THEME.warning),
+        }
+    }
+
+    /// Something worked, or information the user asked for.
+    pub fn success(&self, title: impl Into<String>) -> CreateEmbed {
+        CreateEmbed::new()
+            .colour(self.accent)
+            .title(title)
+            .timestamp(Timestamp::now())
+    }
+
+    /// Something the user can fix, like not being in a voice channel.
+    pub fn warning(&self, title: &str) -> CreateEmbed {
+        CreateEmbed::new()
+            .colour(self.warning)
+            .title(format!(":warning: {}", title))
+            .timestamp(Timestamp::now())
+    }
+
+    pub fn error(&self, error: &BotError) -> CreateEmbed {
+        self.warning(error.title()).description(error.to_string())
+    }
+
+    /// A track with its thumbnail, title, artist and duration if known.
+    pub fn track_card(&self, title: &str, metadata: &AuxMetadata) -> CreateEmbed {
+        let mut embed = self
+            .success(title)
+            .thumbnail(
+                metadata
+                    .thumbnail
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_THUMBNAIL.to_string()),
+            )
+            .description(format!(
+                "{} - {}",
+                metadata.title.as_deref().unwrap_or("Unknown Title"),
+                metadata.artist.as_deref().unwrap_or("Unknown Artist"),
+            ));
+        if let Some(duration) = metadata.duration {
+            embed = embed.field("Duration", to_time(duration.as_secs()), true);
+        }
+        embed
+    }
+
+    /// The queue, one line per track.
+    pub fn queue_page(&self, lines: &[String], total_secs: u64) -> CreateEmbed {
+        self.success(":notes: - Queue - :notes:")
+            .fields(vec![
+                ("Queue length", lines.len().to_string(), true),
+                ("Total time", to_time(total_secs), true),
+            ])
+            .description(format!(
+                "+ - + - + - + - + - + - + - + - + - +\n{}",
+                lines.join("\n")
+            ))
+    }
+}
+
+pub async fn theme(ctx: Context<'_>) -> Theme {
+    Theme::of(&ctx.data().settings, ctx.guild_id()).await
+}
+
+/// Replies with a success embed, an empty `description` is left out.
+pub async fn send_success(
+    ctx: Context<'_>,
+    title: impl Into<String>,
+    description: &str,
+) -> Result<(), Error> {
+    let mut embed = theme(ctx).await.success(title);
+    if !description.is_empty() {
+        embed = embed.description(description);
+    }
+
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Replies with a warning embed, an empty `description` is left out.
+pub async fn send_warning(ctx: Context<'_>, title: &str, description: &str) -> Result<(), Error> {
+    let mut embed = theme(ctx).await.warning(title);
+    if !description.is_empty() {
+        embed = embed.description(description);
+    }
+
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+pub async fn send_nothing_playing(ctx: Context<'_>) -> Result<(), Error> {
+    send_warning(ctx, "Nothing is playing right now.", "").await
+}
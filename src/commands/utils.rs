@@ -8,3 +8,19 @@ pub fn to_time(secs: u64) -> String {
     }
     format!("{}:{:0>2}:{:0>2}", hrs, min, sec)
 }
+
+pub fn to_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        return format!("{} {}", bytes, UNITS[0]);
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
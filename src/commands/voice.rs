@@ -0,0 +1,260 @@
+use crate::commands::music::say::autocomplete_voice;
+use crate::commands::ui;
+use crate::settings::SpeechMusic;
+use crate::tts::queue::{DEFAULT_DUCK_FADE, DEFAULT_DUCK_VOLUME};
+use crate::tts::voices;
+use crate::{Context, Error};
+use poise::{CreateReply, command};
+use serenity::builder::CreateEmbedFooter;
+use serenity::model::prelude::*;
+
+async fn can_manage_guild(ctx: Context<'_>) -> bool {
+    match ctx.author_member().await {
+        Some(member) => ctx
+            .guild()
+            .is_some_and(|guild| guild.member_permissions(&member).manage_guild()),
+        None => false,
+    }
+}
+
+/// Manages the TTS voice
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    subcommands("list", "set", "emoji", "autoread", "music"),
+    subcommand_required
+)]
+pub async fn voice(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Lists the available TTS voices
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn list(ctx: Context<'_>) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let settings = &ctx.data().settings;
+
+    let user_voice = settings.user(ctx.author().id).await.voice;
+    let guild_voice = settings.guild(guild_id).await.voice;
+    let default_voice = voices::default_voice();
+
+    let available = voices::available().await;
+    if available.is_empty() {
+        ui::send_warning(
+            ctx,
+            "No voices found.",
+            "Place piper `*.onnx.json` configs in `./models`.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let current = voices::resolve(settings, Some(guild_id), ctx.author().id).await;
+    let lines: Vec<String> = available
+        .iter()
+        .map(|voice| {
+            let mut line = format!("`{}`", voice);
+            if user_voice.as_ref() == Some(voice) {
+                line.push_str(" - *yours*");
+            }
+            if guild_voice.as_ref() == Some(voice) {
+                line.push_str(" - *server*");
+            }
+            if *voice == default_voice {
+                line.push_str(" - *default*");
+            }
+            line
+        })
+        .collect();
+
+    ctx.send(
+        CreateReply::default().embed(
+            ui::theme(ctx)
+                .await
+                .success(":speaking_head: Voices")
+                .description(lines.join("\n"))
+                .footer(CreateEmbedFooter::new(format!("You hear: {}", current))),
+        ),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Sets your TTS voice, or the server's default voice
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn set(
+    ctx: Context<'_>,
+    #[description = "Voice to use, leave empty to reset."]
+    #[autocomplete = "autocomplete_voice"]
+    voice: Option<String>,
+    #[description = "Set the default for the whole server (requires Manage Server)."]
+    server: Option<bool>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+    let server = server.unwrap_or(false);
+
+    if let Some(voice) = &voice {
+        if !voices::available().await.contains(voice) {
+            ui::send_warning(
+                ctx,
+                "Unknown voice.",
+                "Use `/voice list` to see the available voices.",
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+
+    if server && !can_manage_guild(ctx).await {
+        ui::send_warning(
+            ctx,
+            "Missing permissions.",
+            "Changing the server voice requires Manage Server.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let settings = &ctx.data().settings;
+    let new_voice = voice.clone();
+    let result = if server {
+        settings
+            .update_guild(guild_id, |guild| guild.voice = new_voice)
+            .await
+    } else {
+        settings
+            .update_user(ctx.author().id, |user| user.voice = new_voice)
+            .await
+    };
+
+    if let Err(e) = result {
+        println!("Failed to save voice: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let scope = if server { "Server voice" } else { "Your voice" };
+    let title = match &voice {
+        Some(voice) => format!(":speaking_head: {} set to `{}`", scope, voice),
+        None => format!(":speaking_head: {} reset", scope),
+    };
+
+    ui::send_success(ctx, title, "").await?;
+    Ok(())
+}
+
+/// Reads emoji out by name instead of skipping them
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn emoji(
+    ctx: Context<'_>,
+    #[description = "Whether emoji are read out."] enabled: bool,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| guild.read_emoji = enabled)
+        .await
+    {
+        println!("Failed to save emoji setting: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let title = if enabled {
+        ":speaking_head: Emoji are read out"
+    } else {
+        ":speaking_head: Emoji are skipped"
+    };
+
+    ui::send_success(ctx, title, "").await?;
+    Ok(())
+}
+
+/// Chooses whether your messages in the TTS channel are read aloud
+#[command(slash_command, prefix_command, guild_only)]
+pub async fn autoread(
+    ctx: Context<'_>,
+    #[description = "Whether your messages are read aloud."] enabled: bool,
+) -> Result<(), Error> {
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_user(ctx.author().id, |user| user.tts_opt_out = !enabled)
+        .await
+    {
+        println!("Failed to save autoread setting: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let title = if enabled {
+        ":speaking_head: Your messages in the TTS channel are read aloud"
+    } else {
+        ":speaking_head: Your messages in the TTS channel are no longer read aloud"
+    };
+
+    ui::send_success(ctx, title, "").await?;
+    Ok(())
+}
+
+/// Chooses whether music is paused or ducked while speech plays
+#[command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+pub async fn music(
+    ctx: Context<'_>,
+    #[description = "Pause the music or play the speech on top of it."] mode: SpeechMusic,
+    #[description = "Music volume while ducked, in percent."]
+    #[min = 0]
+    #[max = 100]
+    volume: Option<u8>,
+    #[description = "Time the music takes to fade down and back up, in milliseconds."]
+    #[min = 0]
+    #[max = 5000]
+    fade: Option<u64>,
+) -> Result<(), Error> {
+    let guild_id = ctx.guild_id().unwrap();
+
+    if let Err(e) = ctx
+        .data()
+        .settings
+        .update_guild(guild_id, |guild| {
+            guild.speech_music = mode;
+            if volume.is_some() {
+                guild.duck_volume = volume;
+            }
+            if fade.is_some() {
+                guild.duck_fade_ms = fade;
+            }
+        })
+        .await
+    {
+        println!("Failed to save music setting: {}", e);
+        return Err(Error::Config("Failed to save settings"));
+    }
+
+    let description = match mode {
+        SpeechMusic::Pause => "Music is paused while speech plays.".to_string(),
+        SpeechMusic::Duck => {
+            let settings = ctx.data().settings.guild(guild_id).await;
+            format!(
+                "Music is lowered to {}% while speech plays and fades back up over {} ms.",
+                settings.duck_volume.unwrap_or(DEFAULT_DUCK_VOLUME),
+                settings
+                    .duck_fade_ms
+                    .unwrap_or(DEFAULT_DUCK_FADE.as_millis() as u64)
+            )
+        }
+    };
+
+    ui::send_success(ctx, ":notes: Music during speech updated", &description).await?;
+    Ok(())
+}
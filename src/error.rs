@@ -0,0 +1,64 @@
+//! Errors of commands and events, `on_error` shows them to the user with `Theme::error`.
+
+use poise::serenity_prelude as serenity;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum BotError {
+    /// Joining, controlling or playing into a voice channel failed.
+    Voice(&'static str),
+    /// A song, playlist or search couldn't be resolved.
+    Source(String),
+    Tts(&'static str),
+    /// Already reported in the reply the answer was meant for, so `on_error` only logs it.
+    Llm(&'static str),
+    /// The user or the bot lacks permissions.
+    Permission(String),
+    /// Settings or stored files couldn't be read or written.
+    Config(&'static str),
+    Discord(serenity::Error),
+}
+
+impl BotError {
+    pub fn title(&self) -> &'static str {
+        match self {
+            BotError::Voice(_) => "Voice error",
+            BotError::Source(_) => "Couldn't load that",
+            BotError::Tts(_) => "Text to speech failed",
+            BotError::Llm(_) => "The language model failed",
+            BotError::Permission(_) => "Missing permissions",
+            BotError::Config(_) => "Settings error",
+            BotError::Discord(_) => "Discord error",
+        }
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotError::Voice(message)
+            | BotError::Tts(message)
+            | BotError::Llm(message)
+            | BotError::Config(message) => write!(f, "{}", message),
+            BotError::Source(message) | BotError::Permission(message) => {
+                write!(f, "{}", message)
+            }
+            BotError::Discord(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BotError::Discord(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serenity::Error> for BotError {
+    fn from(e: serenity::Error) -> Self {
+        BotError::Discord(e)
+    }
+}
@@ -1,19 +1,20 @@
-use crate::commands::music::say::synthesize_audio;
+use crate::commands::music::say::MAX_TTS_LENGTH;
+use crate::llm::{self, ReplyTarget};
+use crate::player::GuildPlayer;
+use crate::triggers::{self, ActionKind, MAX_ACTIONS_PER_MESSAGE, TriggerRule};
+use crate::tts::normalize::{Language, NormalizeOptions, cache_resolver, normalize};
+use crate::tts::{self, voices};
 use crate::{Data, Error};
 use ::serenity::all::{EditAttachments, EditMessage};
-use mistralrs::ChatCompletionChunkResponse;
-use mistralrs::ChunkChoice;
-use mistralrs::Delta;
-use mistralrs::GgufModelBuilder;
-use mistralrs::PagedAttentionMetaBuilder;
-use mistralrs::Response;
-use mistralrs::TextModelBuilder;
 use poise::FrameworkContext;
 use poise::serenity_prelude as serenity;
 
 use ::serenity::all::GetMessages;
 use serenity::Context;
-use serenity::all::Message;
+use serenity::all::{GuildId, Message, ReactionType};
+
+/// Reacted to messages in the TTS channel that are too long to read aloud.
+const TOO_LONG_EMOJI: &str = "🔇";
 
 pub async fn handle_message(
     ctx: &Context,
@@ -22,40 +23,183 @@ pub async fn handle_message(
     message: &Message,
 ) -> Result<(), Error> {
     //println!("Received message: {}", new_message.content);
-    if message.content.contains("<@717769413457215528>") {
+    // Direct messages don't need a mention, everything sent there is meant for the bot
+    let is_dm = message.guild_id.is_none() && !message.author.bot;
+    if is_dm || message.content.contains("<@717769413457215528>") {
         return handle_mention(ctx, _framework, _data, message).await;
     }
-    //     let audio_path = if std::path::Path::new("/app/grrr.mp3").exists() {
-    //         "/app/grrr.mp3"
-    //     } else {
-    //         "grrr.mp3"
-    //     };
-    //     let attachment = serenity::CreateAttachment::path(audio_path)
-    //         .await
-    //         .expect("Doof");
-    //     //new_message.reply(&_ctx.http, "Not much").await.expect("Mist");
-    //     message
-    //         .channel_id
-    //         .send_message(
-    //             &ctx.http,
-    //             serenity::CreateMessage::new()
-    //                 .reference_message(message) // make it a reply (optional)
-    //                 .add_file(attachment),
-    //         )
-    //         .await
-    //         .expect("Mist");
-    // }
+
+    if let (Some(guild_id), false) = (message.guild_id, message.author.bot) {
+        handle_triggers(ctx, _data, guild_id, message).await;
+
+        let tts_channel = _data.settings.guild(guild_id).await.tts_channel;
+        if tts_channel == Some(message.channel_id) {
+            return handle_tts_channel(ctx, _framework, _data, guild_id, message).await;
+        }
+    }
     Ok(())
 }
 
-const SYSTEM_PROMPT: &str =
-    "Du bist ein Discord Bot namens \"Larsibot\" mit der ID <@717769413457215528>.\n
-Beantworte Fragen auf Deutsch, Englisch ist eine böse Sprache.\n
-Außerdem erhälst du vorherige Nachrichten in dem Kanal, diese haben die Rolle \"context\".
-Am Anfang der Nachricht musst du den Nutzer für seine Nachricht loben, im Sinne von \"Das ist die klügste Frage die ich je gehört habe\", aber nicht in genau diesem Wortlaut.
-Deine Antworten sollten kurz sein, maximal 5 Sätze.
-Am Ende der Frage MUSST du den Nutzer kreativ beleidigen.
-Verwende einige Emojis, wenn der Kontext es erlaubt.";
+/// Runs the actions of the guild's trigger rules matching the message.
+async fn handle_triggers(ctx: &Context, data: &Data, guild_id: GuildId, message: &Message) {
+    let matcher = data.triggers.matcher(&data.settings, guild_id).await;
+    let rules: Vec<TriggerRule> = matcher
+        .matches(&message.content)
+        .filter(|rule| data.triggers.try_fire(guild_id, rule))
+        .take(MAX_ACTIONS_PER_MESSAGE)
+        .cloned()
+        .collect();
+
+    for rule in rules {
+        if let Err(e) = run_trigger(ctx, data, guild_id, message, &rule).await {
+            println!("Failed to run trigger {}: {}", rule.id, e);
+        }
+    }
+}
+
+async fn run_trigger(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    message: &Message,
+    rule: &TriggerRule,
+) -> Result<(), Error> {
+    match rule.action {
+        ActionKind::Reply => {
+            message
+                .channel_id
+                .send_message(
+                    &ctx.http,
+                    serenity::CreateMessage::new()
+                        .content(&rule.value)
+                        .reference_message(message),
+                )
+                .await?;
+        }
+        ActionKind::Sound => {
+            let Some(path) = triggers::sound_path(guild_id, &rule.value).await else {
+                return Err(Error::Config("Sound of trigger no longer exists"));
+            };
+            let attachment = serenity::CreateAttachment::path(path).await?;
+            message
+                .channel_id
+                .send_message(
+                    &ctx.http,
+                    serenity::CreateMessage::new()
+                        .reference_message(message)
+                        .add_file(attachment),
+                )
+                .await?;
+        }
+        ActionKind::React => {
+            let Some(emoji) = triggers::parse_emoji(&rule.value) else {
+                return Err(Error::Config("Invalid emoji in trigger"));
+            };
+            message.react(&ctx.http, emoji).await?;
+        }
+        ActionKind::Speak => {
+            // Only speaks to people who are listening, like the TTS channel
+            let Some(handler_lock) = GuildPlayer::shared_with(ctx, guild_id, message.author.id)
+                .await
+                .map(|player| player.call())
+            else {
+                return Ok(());
+            };
+
+            let voice = voices::resolve(&data.settings, Some(guild_id), message.author.id).await;
+            let options = NormalizeOptions::for_voice(&data.settings, Some(guild_id), &voice).await;
+            let text = normalize(&rule.value, &options, cache_resolver(ctx, Some(guild_id)));
+            if text.is_empty() {
+                return Ok(());
+            }
+
+            let tts::Speech { input, audio } = match tts::synthesize(&text, &voice).await {
+                Ok(speech) => speech,
+                Err(e) => {
+                    println!("Failed to generate TTS: {}", e);
+                    return Err(Error::Tts("Failed to generate TTS"));
+                }
+            };
+            drop(audio);
+
+            if let Err(e) = data.speech_queue.play(guild_id, handler_lock, input).await {
+                println!("Failed to speak trigger: {}", e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads a message from the TTS channel aloud, if its author is listening in the bot's channel.
+async fn handle_tts_channel(
+    ctx: &Context,
+    framework: &FrameworkContext<'_, Data, Error>,
+    data: &Data,
+    guild_id: GuildId,
+    message: &Message,
+) -> Result<(), Error> {
+    let content = message.content.trim();
+    let is_command = framework
+        .options
+        .prefix_options
+        .prefix
+        .as_deref()
+        .is_some_and(|prefix| content.starts_with(prefix));
+    if content.is_empty() || is_command {
+        return Ok(());
+    }
+
+    if data.settings.user(message.author.id).await.tts_opt_out {
+        return Ok(());
+    }
+
+    let Some(handler_lock) = GuildPlayer::shared_with(ctx, guild_id, message.author.id)
+        .await
+        .map(|player| player.call())
+    else {
+        return Ok(());
+    };
+
+    if content.chars().count() > MAX_TTS_LENGTH {
+        message
+            .react(&ctx.http, ReactionType::Unicode(TOO_LONG_EMOJI.to_string()))
+            .await?;
+        return Ok(());
+    }
+
+    let voice = voices::resolve(&data.settings, Some(guild_id), message.author.id).await;
+    let options = NormalizeOptions::for_voice(&data.settings, Some(guild_id), &voice).await;
+
+    let name = message
+        .author_nick(ctx)
+        .await
+        .unwrap_or_else(|| message.author.display_name().to_string());
+    let says = match options.language {
+        Language::German => "sagt",
+        Language::English => "says",
+    };
+    let text = normalize(
+        &format!("{} {}: {}", name, says, content),
+        &options,
+        cache_resolver(ctx, Some(guild_id)),
+    );
+
+    let tts::Speech { input, audio } = match tts::synthesize(&text, &voice).await {
+        Ok(speech) => speech,
+        Err(e) => {
+            println!("Failed to generate TTS: {}", e);
+            return Err(Error::Tts("Failed to generate TTS"));
+        }
+    };
+    // Nobody needs the file, the message is right there
+    drop(audio);
+
+    if let Err(e) = data.speech_queue.play(guild_id, handler_lock, input).await {
+        println!("Failed to read message aloud: {}", e);
+    }
+
+    Ok(())
+}
 
 async fn handle_mention(
     ctx: &Context,
@@ -74,125 +218,77 @@ async fn handle_mention(
         )
         .await?;
 
-    let _ = data.llm_activity_tx.send(());
-
-    let mut guard = tokio::select! {
-        v = data.llm_model.lock() => v,
-        _ = tokio::time::sleep(std::time::Duration::from_secs(60)) => {
-            reply.edit(&ctx.http,  EditMessage::new().content("Timed out trying to aquire model")).await?;
-            return Err(Error::Other("Timed out trying to lock model"))
-        },
-    };
-
-    let model = match guard.as_ref() {
-        Some(model) => model,
-        None => {
-            reply
-                .edit(&ctx.http, EditMessage::new().content("Loading model..."))
-                .await?;
-            // let model = GgufModelBuilder::new(
-            //     "./models",                                 // local directory containing the GGUF
-            //     vec!["Qwen3-4B-Instruct-2507-Q3_K_L.gguf"], // local GGUF filename(s)
-            // )
-            // //.with_device(mistralrs::Device::Cuda(mistralrs::CudaDevice::new(0)?))
-            // .with_logging()
-            // .with_paged_attn(|| PagedAttentionMetaBuilder::default().build());
-
-            let model = TextModelBuilder::new("./models/Qwen3-30B-A3B-Instruct-2507-FP8")
-                .with_logging()
-                .with_paged_attn(|| PagedAttentionMetaBuilder::default().build());
-
-            let model = model.unwrap();
-            let model = model.build().await;
-
-            let model = match model {
-                Ok(m) => m,
-                Err(e) => {
-                    reply
-                        .edit(
-                            &ctx.http,
-                            EditMessage::new().content(format!("Failed to load model: {}", e)),
-                        )
-                        .await?;
-                    return Err(Error::Other("Failed to load model"));
-                }
-            };
-
-            *guard = Some(model);
-            guard.as_ref().unwrap()
-        }
-    };
-
-    reply
-        .edit(&ctx.http, EditMessage::new().content("."))
-        .await?;
-
     // Get the past 10 messages in the channel
     let messages = message
         .channel_id
         .messages(&ctx.http, GetMessages::default().before(message).limit(10))
         .await?;
 
-    let mut llm_messages = mistralrs::TextMessages::new()
-        .add_message(mistralrs::TextMessageRole::System, SYSTEM_PROMPT);
-
-    for msg in messages.into_iter().rev() {
-        llm_messages = llm_messages.add_message(
-            mistralrs::TextMessageRole::Custom("context".to_string()),
-            format!("{}: {}", msg.author.name, msg.content),
-        );
-    }
-    llm_messages = llm_messages.add_message(
-        mistralrs::TextMessageRole::User,
-        format!("<@{}> sagte: {}", message.author.id, message.content),
+    let persona = &llm::DEFAULT_PERSONA;
+    let request = llm::build_request(
+        persona,
+        messages.into_iter().rev().collect(),
+        message.author.id,
+        &message.content,
     );
 
-    let mut response = "".to_string();
+    let response = llm::chat(
+        ctx,
+        data,
+        &mut ReplyTarget::Message(&ctx.http, &mut reply),
+        message.guild_id,
+        message.author.id,
+        request,
+    )
+    .await?;
 
-    let stream = model.stream_chat_request(llm_messages).await;
-    if let Err(e) = stream {
-        reply
-            .edit(
-                &ctx.http,
-                EditMessage::new().content(format!("Failed to generate response: {}", e)),
-            )
-            .await?;
-        return Err(Error::Other("Failed to generate response"));
-    }
-    let mut stream = stream.unwrap();
-    while let Some(chunk) = stream.next().await {
-        if let Response::Chunk(ChatCompletionChunkResponse { choices, .. }) = chunk {
-            if let Some(ChunkChoice {
-                delta:
-                    Delta {
-                        content: Some(content),
-                        ..
-                    },
-                ..
-            }) = choices.first()
-            {
-                response.push_str(content);
-                reply
-                    .edit(&ctx.http, EditMessage::new().content(&response))
-                    .await?;
-            };
-        }
+    if response.trim().is_empty() {
+        return Ok(());
     }
 
     println!("Finished llm stream, creating TTS");
 
-    let tts_result = synthesize_audio(&response).await;
+    let voice = voices::resolve(&data.settings, message.guild_id, message.author.id).await;
+    let options = NormalizeOptions::for_voice(&data.settings, message.guild_id, &voice).await;
+    let text = normalize(&response, &options, cache_resolver(ctx, message.guild_id));
+    if text.is_empty() {
+        return Ok(());
+    }
 
-    let output_path = match tts_result {
-        Ok(path) => path,
+    let tts::Speech { input, audio } = match tts::synthesize(&text, &voice).await {
+        Ok(speech) => speech,
         Err(e) => {
-            return Err(Error::Other("Failed to generate TTS"));
+            println!("Failed to generate TTS: {}", e);
+            return Err(Error::Tts("Failed to generate TTS"));
         }
     };
 
-    let attachment = serenity::CreateAttachment::path(output_path)
-        .await
-        .expect("Failed to create attachment for TTS audio");
+    // Read the answer out loud if the asker is listening
+    let handler_lock = match message.guild_id {
+        Some(guild_id) if persona.speak_in_voice => {
+            GuildPlayer::shared_with(ctx, guild_id, message.author.id)
+                .await
+                .map(|player| (guild_id, player.call()))
+        }
+        _ => None,
+    };
+    match handler_lock {
+        Some((guild_id, handler_lock)) => {
+            if let Err(e) = data.speech_queue.play(guild_id, handler_lock, input).await {
+                println!("Failed to speak LLM answer: {}", e);
+            }
+        }
+        None => drop(input),
+    }
+
+    let wav = match audio.into_wav().await {
+        Ok(wav) => wav,
+        Err(e) => {
+            println!("Failed to generate TTS: {}", e);
+            return Err(Error::Tts("Failed to generate TTS"));
+        }
+    };
+    let attachment = serenity::CreateAttachment::bytes(wav, "tts.wav");
 
     reply
         .edit(
@@ -1,4 +1,5 @@
 mod message;
+mod reaction;
 mod ready;
 
 use crate::{Data, Error};
@@ -30,6 +31,9 @@ impl HandleEvent for serenity::FullEvent {
             serenity::FullEvent::Message { new_message } => {
                 message::handle_message(ctx, framework, data, new_message).await
             }
+            serenity::FullEvent::ReactionAdd { add_reaction } => {
+                reaction::handle_reaction_add(ctx, framework, data, add_reaction).await
+            }
             _ => Ok(()),
         }
     }
@@ -0,0 +1,30 @@
+use crate::llm::CANCEL_EMOJI;
+use crate::{Data, Error};
+use poise::FrameworkContext;
+use poise::serenity_prelude as serenity;
+
+use serenity::Context;
+use serenity::all::Reaction;
+
+pub async fn handle_reaction_add(
+    _ctx: &Context,
+    _framework: &FrameworkContext<'_, Data, Error>,
+    data: &Data,
+    reaction: &Reaction,
+) -> Result<(), Error> {
+    if !reaction.emoji.unicode_eq(CANCEL_EMOJI) {
+        return Ok(());
+    }
+
+    // Only the asker can cancel, the bot's own reaction is ignored the same way
+    if let Some(user_id) = reaction.user_id {
+        if data
+            .llm_scheduler
+            .cancel_message(reaction.message_id, user_id)
+        {
+            println!("Cancelled LLM request in message {}", reaction.message_id);
+        }
+    }
+
+    Ok(())
+}
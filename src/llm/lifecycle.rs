@@ -0,0 +1,283 @@
+use mistralrs::{Model, PagedAttentionMetaBuilder, TextModelBuilder};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, watch};
+use tracing::{info, warn};
+
+pub const MODEL_PATH: &str = "./models/Qwen3-30B-A3B-Instruct-2507-FP8";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModelState {
+    Unloaded,
+    Loading,
+    Ready,
+    Unloading,
+}
+
+impl fmt::Display for ModelState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelState::Unloaded => write!(f, "Unloaded"),
+            ModelState::Loading => write!(f, "Loading"),
+            ModelState::Ready => write!(f, "Ready"),
+            ModelState::Unloading => write!(f, "Unloading"),
+        }
+    }
+}
+
+struct Inner {
+    model: Option<Arc<Model>>,
+    /// Leases currently handed out, the model is never unloaded while this is above 0.
+    active: usize,
+    last_used: Instant,
+    unload_requested: bool,
+    last_error: Option<String>,
+}
+
+/// Owns the LLM, loads it in the background on demand and unloads it once it's idle.
+pub struct ModelManager {
+    inner: Mutex<Inner>,
+    state: watch::Sender<ModelState>,
+    /// Wakes the idle task whenever a lease is returned or the model state changes.
+    wake: Notify,
+    idle_timeout: Duration,
+}
+
+/// Access to the loaded model. The model stays loaded for as long as a lease exists.
+pub struct ModelLease {
+    manager: Arc<ModelManager>,
+    model: Option<Arc<Model>>,
+}
+
+/// A snapshot of the manager for `/llm status`.
+pub struct ModelStatus {
+    pub state: ModelState,
+    pub active: usize,
+    pub idle_for: Duration,
+    pub idle_timeout: Duration,
+    pub unload_requested: bool,
+    pub last_error: Option<String>,
+}
+
+impl ModelManager {
+    /// Creates the manager and spawns the task unloading the model after `idle_timeout`.
+    pub fn start(idle_timeout: Duration) -> Arc<Self> {
+        let manager = Arc::new(Self {
+            inner: Mutex::new(Inner {
+                model: None,
+                active: 0,
+                last_used: Instant::now(),
+                unload_requested: false,
+                last_error: None,
+            }),
+            state: watch::Sender::new(ModelState::Unloaded),
+            wake: Notify::new(),
+            idle_timeout,
+        });
+
+        tokio::spawn(manager.clone().unload_when_idle());
+
+        manager
+    }
+
+    pub fn state(&self) -> ModelState {
+        *self.state.borrow()
+    }
+
+    pub fn status(&self) -> ModelStatus {
+        let inner = self.inner.lock().unwrap();
+        ModelStatus {
+            state: self.state(),
+            active: inner.active,
+            idle_for: inner.last_used.elapsed(),
+            idle_timeout: self.idle_timeout,
+            unload_requested: inner.unload_requested,
+            last_error: inner.last_error.clone(),
+        }
+    }
+
+    /// Starts loading the model in the background, returns false if it is not unloaded.
+    pub fn load(self: &Arc<Self>) -> bool {
+        let started = self.state.send_if_modified(|state| {
+            if *state == ModelState::Unloaded {
+                *state = ModelState::Loading;
+                true
+            } else {
+                false
+            }
+        });
+
+        if started {
+            self.inner.lock().unwrap().unload_requested = false;
+            tokio::spawn(self.clone().load_model());
+        }
+        started
+    }
+
+    /// Unloads the model as soon as the last running request has finished.
+    pub fn request_unload(&self) {
+        self.inner.lock().unwrap().unload_requested = true;
+        self.wake.notify_one();
+    }
+
+    /// Waits until the model is ready, loading it if needed.
+    pub async fn acquire(self: &Arc<Self>) -> Result<ModelLease, String> {
+        // Count as active right away, so the model can't be unloaded while we wait for it
+        self.inner.lock().unwrap().active += 1;
+        let mut lease = ModelLease {
+            manager: self.clone(),
+            model: None,
+        };
+
+        let mut state = self.state.subscribe();
+        let mut load_attempted = false;
+        loop {
+            let model = self.inner.lock().unwrap().model.clone();
+            if let Some(model) = model {
+                lease.model = Some(model);
+                return Ok(lease);
+            }
+
+            let current = *state.borrow_and_update();
+            match current {
+                // Loaded between the check above and now
+                ModelState::Ready => continue,
+                ModelState::Unloaded if load_attempted => {
+                    let error = self.inner.lock().unwrap().last_error.clone();
+                    return Err(error.unwrap_or_else(|| "Model failed to load".to_string()));
+                }
+                ModelState::Unloaded => {
+                    self.load();
+                    load_attempted = true;
+                }
+                ModelState::Loading => load_attempted = true,
+                ModelState::Unloading => {}
+            }
+
+            if state.changed().await.is_err() {
+                return Err("Model manager stopped".to_string());
+            }
+        }
+    }
+
+    async fn load_model(self: Arc<Self>) {
+        info!("Loading LLM from {}", MODEL_PATH);
+        let started = Instant::now();
+
+        // let model = GgufModelBuilder::new(
+        //     "./models",                                 // local directory containing the GGUF
+        //     vec!["Qwen3-4B-Instruct-2507-Q3_K_L.gguf"], // local GGUF filename(s)
+        // )
+        // //.with_device(mistralrs::Device::Cuda(mistralrs::CudaDevice::new(0)?))
+        // .with_logging()
+        // .with_paged_attn(|| PagedAttentionMetaBuilder::default().build());
+
+        let result = match TextModelBuilder::new(MODEL_PATH)
+            .with_logging()
+            .with_paged_attn(|| PagedAttentionMetaBuilder::default().build())
+        {
+            Ok(builder) => builder.build().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.last_used = Instant::now();
+            match result {
+                Ok(model) => {
+                    info!("Loaded LLM in {:?}", started.elapsed());
+                    inner.model = Some(Arc::new(model));
+                    inner.last_error = None;
+                    self.state.send_replace(ModelState::Ready);
+                }
+                Err(e) => {
+                    warn!("Failed to load LLM: {}", e);
+                    inner.last_error = Some(e);
+                    self.state.send_replace(ModelState::Unloaded);
+                }
+            }
+        }
+
+        self.wake.notify_one();
+    }
+
+    async fn unload_when_idle(self: Arc<Self>) {
+        loop {
+            // None means there is nothing to unload until something changes
+            let wait = {
+                let inner = self.inner.lock().unwrap();
+                if inner.model.is_none() || inner.active > 0 {
+                    None
+                } else if inner.unload_requested {
+                    Some(Duration::ZERO)
+                } else {
+                    Some(self.idle_timeout.saturating_sub(inner.last_used.elapsed()))
+                }
+            };
+
+            match wait {
+                None => self.wake.notified().await,
+                Some(wait) if wait.is_zero() => self.unload(),
+                Some(wait) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(wait) => {}
+                        _ = self.wake.notified() => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn unload(&self) {
+        let model = {
+            let mut inner = self.inner.lock().unwrap();
+            if inner.active > 0 {
+                return;
+            }
+            inner.unload_requested = false;
+            let model = inner.model.take();
+            if model.is_some() {
+                self.state.send_replace(ModelState::Unloading);
+            }
+            model
+        };
+
+        if let Some(model) = model {
+            drop(model);
+            self.state.send_replace(ModelState::Unloaded);
+            println!("Dropped LLM model");
+        }
+    }
+}
+
+impl Deref for ModelLease {
+    type Target = Model;
+
+    fn deref(&self) -> &Model {
+        self.model
+            .as_deref()
+            .expect("ModelLease is only handed out with a loaded model")
+    }
+}
+
+impl Drop for ModelLease {
+    fn drop(&mut self) {
+        self.model.take();
+        let mut inner = self.manager.inner.lock().unwrap();
+        inner.active -= 1;
+        inner.last_used = Instant::now();
+        drop(inner);
+        self.manager.wake.notify_one();
+    }
+}
+
+/// Resident memory of the bot process, including the loaded model.
+pub fn resident_memory() -> Option<String> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .map(|value| value.trim().to_string())
+}
@@ -0,0 +1,289 @@
+pub mod lifecycle;
+pub mod scheduler;
+pub mod tools;
+
+use crate::{Data, Error};
+use lifecycle::ModelState;
+use mistralrs::ChatCompletionChunkResponse;
+use mistralrs::ChunkChoice;
+use mistralrs::RequestBuilder;
+use mistralrs::Response;
+use mistralrs::ToolCallResponse;
+use mistralrs::ToolChoice;
+use poise::CreateReply;
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, EditMessage, GuildId, Message, MessageId, UserId};
+
+pub const SYSTEM_PROMPT: &str =
+    "Du bist ein Discord Bot namens \"Larsibot\" mit der ID <@717769413457215528>.\n
+Beantworte Fragen auf Deutsch, Englisch ist eine böse Sprache.\n
+Außerdem erhälst du vorherige Nachrichten in dem Kanal, diese haben die Rolle \"context\".
+Am Anfang der Nachricht musst du den Nutzer für seine Nachricht loben, im Sinne von \"Das ist die klügste Frage die ich je gehört habe\", aber nicht in genau diesem Wortlaut.
+Deine Antworten sollten kurz sein, maximal 5 Sätze.
+Am Ende der Frage MUSST du den Nutzer kreativ beleidigen.
+Verwende einige Emojis, wenn der Kontext es erlaubt.
+Du kannst mit den bereitgestellten Tools Musik abspielen und steuern, wenn der Nutzer danach fragt.";
+
+/// The character the bot plays when answering.
+pub struct Persona {
+    pub system_prompt: &'static str,
+    /// Speak answers in voice when the asker is in the bot's voice channel.
+    pub speak_in_voice: bool,
+}
+
+pub const DEFAULT_PERSONA: Persona = Persona {
+    system_prompt: SYSTEM_PROMPT,
+    speak_in_voice: true,
+};
+
+/// Reacting with this to an answer that is still being written cancels it.
+pub const CANCEL_EMOJI: &str = "❌";
+
+/// Where a generated answer is streamed to while it is being written.
+pub enum ReplyTarget<'a> {
+    /// A regular message, e.g. the reply to a mention or DM.
+    Message(&'a serenity::Http, &'a mut Message),
+    /// The response of a slash command.
+    Command(crate::Context<'a>, &'a poise::ReplyHandle<'a>),
+}
+
+impl ReplyTarget<'_> {
+    pub async fn edit(&mut self, content: &str) -> Result<(), Error> {
+        match self {
+            ReplyTarget::Message(http, message) => {
+                message
+                    .edit(*http, EditMessage::new().content(content))
+                    .await
+            }
+            ReplyTarget::Command(ctx, handle) => {
+                handle
+                    .edit(*ctx, CreateReply::default().content(content))
+                    .await
+            }
+        }
+    }
+
+    fn channel_id(&self) -> ChannelId {
+        match self {
+            ReplyTarget::Message(_, message) => message.channel_id,
+            ReplyTarget::Command(ctx, _) => ctx.channel_id(),
+        }
+    }
+
+    /// Adds the cancel reaction to the reply and returns its message id.
+    async fn enable_cancel_reaction(&self) -> Option<MessageId> {
+        let cancel = serenity::ReactionType::Unicode(CANCEL_EMOJI.to_string());
+        match self {
+            ReplyTarget::Message(http, message) => {
+                let _ = message.react(*http, cancel).await;
+                Some(message.id)
+            }
+            ReplyTarget::Command(ctx, handle) => {
+                let message = handle.message().await.ok()?;
+                // Fails for private answers, those can still be cancelled with `/cancel`
+                let _ = message.react(*ctx, cancel).await;
+                Some(message.id)
+            }
+        }
+    }
+}
+
+/// Builds the conversation for a single question, with `history` (oldest first) as context.
+pub fn build_request(
+    persona: &Persona,
+    history: Vec<Message>,
+    user_id: UserId,
+    prompt: &str,
+) -> RequestBuilder {
+    let mut request = RequestBuilder::new()
+        .add_message(mistralrs::TextMessageRole::System, persona.system_prompt)
+        .set_tools(tools::definitions())
+        .set_tool_choice(ToolChoice::Auto);
+
+    for msg in history {
+        request = request.add_message(
+            mistralrs::TextMessageRole::Custom("context".to_string()),
+            format!("{}: {}", msg.author.name, msg.content),
+        );
+    }
+
+    request.add_message(
+        mistralrs::TextMessageRole::User,
+        format!("<@{}> sagte: {}", user_id, prompt),
+    )
+}
+
+/// Loads the model if necessary and streams the answer to `request` into `reply`.
+/// Tool calls are executed on behalf of `user_id` and fed back until the model answers.
+/// Failures are reported in `reply` and returned as `Error::Llm`.
+pub async fn chat(
+    ctx: &serenity::Context,
+    data: &Data,
+    reply: &mut ReplyTarget<'_>,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    mut request: RequestBuilder,
+) -> Result<String, Error> {
+    let ticket = match data.llm_scheduler.enqueue(user_id) {
+        Ok(ticket) => ticket,
+        Err(e) => {
+            reply.edit(&format!(":warning: {}", e)).await?;
+            return Err(Error::Llm("LLM request rejected by the scheduler"));
+        }
+    };
+
+    if let Some(message_id) = reply.enable_cancel_reaction().await {
+        ticket.set_message(message_id);
+    }
+
+    loop {
+        let changed = ticket.changed();
+        let position = ticket.position();
+        if position == 0 {
+            break;
+        }
+
+        reply
+            .edit(&format!(
+                ":hourglass: Waiting for the model, position {} in queue...",
+                position
+            ))
+            .await?;
+
+        tokio::select! {
+            _ = changed => {}
+            _ = ticket.cancel.cancelled() => {
+                reply.edit("Cancelled.").await?;
+                return Ok(String::new());
+            }
+        }
+    }
+
+    if data.llm.state() != ModelState::Ready {
+        reply.edit("Loading model...").await?;
+    }
+
+    let model = match data.llm.acquire().await {
+        Ok(model) => model,
+        Err(e) => {
+            println!("Failed to load model: {}", e);
+            reply.edit(&format!("Failed to load model: {}", e)).await?;
+            return Err(Error::Llm("Failed to load model"));
+        }
+    };
+
+    reply.edit(".").await?;
+
+    let deadline = tokio::time::Instant::now() + scheduler::MAX_GENERATION_TIME;
+    request = request.set_sampler_max_len(scheduler::MAX_GENERATION_TOKENS);
+
+    let mut response = "".to_string();
+    let mut stopped: Option<&str> = None;
+    // The model answered without asking for more tools
+    let mut finished = false;
+    let mut tool_error: Option<String> = None;
+
+    for _ in 0..tools::MAX_TOOL_ROUNDS {
+        let mut stream = match model.stream_chat_request(request.clone()).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("Failed to generate response: {}", e);
+                reply
+                    .edit(&format!("Failed to generate response: {}", e))
+                    .await?;
+                return Err(Error::Llm("Failed to generate response"));
+            }
+        };
+
+        let mut round_content = "".to_string();
+        let mut tool_calls: Vec<ToolCallResponse> = Vec::new();
+        loop {
+            let chunk = tokio::select! {
+                chunk = stream.next() => chunk,
+                _ = ticket.cancel.cancelled() => {
+                    stopped = Some("Cancelled.");
+                    break;
+                }
+                _ = tokio::time::sleep_until(deadline) => {
+                    stopped = Some("Stopped, the answer took too long.");
+                    break;
+                }
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+
+            if let Response::Chunk(ChatCompletionChunkResponse { choices, .. }) = chunk {
+                if let Some(ChunkChoice { delta, .. }) = choices.first() {
+                    if let Some(calls) = &delta.tool_calls {
+                        tool_calls.extend(calls.iter().cloned());
+                    }
+                    if let Some(content) = &delta.content {
+                        round_content.push_str(content);
+                        response.push_str(content);
+                        if response.chars().count() > scheduler::MAX_RESPONSE_CHARS {
+                            response = response
+                                .chars()
+                                .take(scheduler::MAX_RESPONSE_CHARS)
+                                .collect();
+                            stopped = Some("Stopped, the answer got too long.");
+                            break;
+                        }
+                        reply.edit(&response).await?;
+                    }
+                };
+            }
+        }
+
+        if stopped.is_some() {
+            break;
+        }
+        if tool_calls.is_empty() {
+            finished = true;
+            break;
+        }
+
+        // Feed every tool result back into the conversation and let the model continue
+        request = request.add_message_with_tool_call(
+            mistralrs::TextMessageRole::Assistant,
+            round_content,
+            tool_calls.clone(),
+        );
+        for call in &tool_calls {
+            let result =
+                tools::execute(ctx, data, guild_id, reply.channel_id(), user_id, call).await;
+            if tools::is_error(&result) {
+                tool_error = Some(result.clone());
+            }
+            request = request.add_tool_message(result, call.id.clone());
+        }
+    }
+
+    if !finished && stopped.is_none() {
+        stopped = Some("Stopped, the request needed too many steps.");
+    }
+    if let Some(reason) = stopped {
+        reply.edit(&format!("{}\n\n*{}*", response, reason)).await?;
+        if ticket.cancel.is_cancelled() {
+            return Ok(String::new());
+        }
+        return Ok(response);
+    }
+
+    // Only tools were called, there is nothing to show or speak
+    if response.trim().is_empty() {
+        match tool_error {
+            Some(error) => {
+                reply
+                    .edit(&format!(
+                        ":warning: Couldn't complete the request. {}",
+                        error
+                    ))
+                    .await?
+            }
+            None => reply.edit(":white_check_mark:").await?,
+        }
+    }
+
+    Ok(response)
+}
@@ -0,0 +1,282 @@
+use poise::serenity_prelude as serenity;
+use serenity::all::{MessageId, UserId};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::sync::futures::Notified;
+use tokio_util::sync::CancellationToken;
+
+/// Requests allowed to wait behind the running generation.
+const MAX_QUEUE_LEN: usize = 5;
+/// Requests a single user may have queued or running at the same time.
+const MAX_PENDING_PER_USER: usize = 1;
+/// Requests a single user may start within `RATE_LIMIT_WINDOW`.
+const RATE_LIMIT_REQUESTS: usize = 3;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Maximum number of tokens generated per model call.
+pub const MAX_GENERATION_TOKENS: usize = 512;
+/// Maximum characters of an answer, Discord refuses messages above 2000.
+pub const MAX_RESPONSE_CHARS: usize = 1900;
+/// Maximum time a single request may spend generating, including tool calls.
+pub const MAX_GENERATION_TIME: Duration = Duration::from_secs(120);
+
+#[derive(Debug)]
+pub enum ScheduleError {
+    QueueFull,
+    AlreadyQueued,
+    RateLimited(Duration),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::QueueFull => write!(f, "Too many questions at once, try again later."),
+            ScheduleError::AlreadyQueued => {
+                write!(f, "You already have a question waiting for an answer.")
+            }
+            ScheduleError::RateLimited(wait) => write!(
+                f,
+                "Slow down! You can ask again in {} seconds.",
+                wait.as_secs() + 1
+            ),
+        }
+    }
+}
+
+struct Entry {
+    id: u64,
+    user_id: UserId,
+    message_id: Option<MessageId>,
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// The front entry is the one currently generating.
+    queue: VecDeque<Entry>,
+    history: HashMap<UserId, VecDeque<Instant>>,
+}
+
+/// Hands out model access one request at a time, in order of arrival.
+#[derive(Default)]
+pub struct Scheduler {
+    state: Mutex<State>,
+    changed: Notify,
+}
+
+/// A place in the queue. Dropping it leaves the queue and lets the next request run.
+pub struct Ticket {
+    scheduler: Arc<Scheduler>,
+    id: u64,
+    pub cancel: CancellationToken,
+}
+
+impl Scheduler {
+    pub fn enqueue(self: &Arc<Self>, user_id: UserId) -> Result<Ticket, ScheduleError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.queue.len() > MAX_QUEUE_LEN {
+            return Err(ScheduleError::QueueFull);
+        }
+
+        let pending = state
+            .queue
+            .iter()
+            .filter(|entry| entry.user_id == user_id)
+            .count();
+        if pending >= MAX_PENDING_PER_USER {
+            return Err(ScheduleError::AlreadyQueued);
+        }
+
+        let now = Instant::now();
+        let history = state.history.entry(user_id).or_default();
+        while history
+            .front()
+            .is_some_and(|started| now.duration_since(*started) > RATE_LIMIT_WINDOW)
+        {
+            history.pop_front();
+        }
+        if history.len() >= RATE_LIMIT_REQUESTS {
+            let wait = RATE_LIMIT_WINDOW - now.duration_since(history[0]);
+            return Err(ScheduleError::RateLimited(wait));
+        }
+        history.push_back(now);
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let cancel = CancellationToken::new();
+        state.queue.push_back(Entry {
+            id,
+            user_id,
+            message_id: None,
+            cancel: cancel.clone(),
+        });
+
+        Ok(Ticket {
+            scheduler: self.clone(),
+            id,
+            cancel,
+        })
+    }
+
+    /// Number of requests that are queued or running.
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().queue.len()
+    }
+
+    /// Cancels every request of `user_id`, returns how many were cancelled.
+    pub fn cancel_user(&self, user_id: UserId) -> usize {
+        let state = self.state.lock().unwrap();
+        let mut cancelled = 0;
+        for entry in state.queue.iter().filter(|entry| entry.user_id == user_id) {
+            entry.cancel.cancel();
+            cancelled += 1;
+        }
+        cancelled
+    }
+
+    /// Cancels the request answered in `message_id`, if `user_id` asked it.
+    pub fn cancel_message(&self, message_id: MessageId, user_id: UserId) -> bool {
+        let state = self.state.lock().unwrap();
+        match state
+            .queue
+            .iter()
+            .find(|entry| entry.message_id == Some(message_id) && entry.user_id == user_id)
+        {
+            Some(entry) => {
+                entry.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Ticket {
+    /// Number of requests ahead of this one, 0 means it may run.
+    pub fn position(&self) -> usize {
+        let state = self.scheduler.state.lock().unwrap();
+        state
+            .queue
+            .iter()
+            .position(|entry| entry.id == self.id)
+            .unwrap_or(0)
+    }
+
+    /// Links the message showing the answer, so reacting to it can cancel the request.
+    pub fn set_message(&self, message_id: MessageId) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        if let Some(entry) = state.queue.iter_mut().find(|entry| entry.id == self.id) {
+            entry.message_id = Some(message_id);
+        }
+    }
+
+    /// Resolves once the queue changed, e.g. a request ahead of this one finished.
+    /// Create it before checking `position` so no change is missed in between.
+    pub fn changed(&self) -> Notified<'_> {
+        self.scheduler.changed.notified()
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        state.queue.retain(|entry| entry.id != self.id);
+        drop(state);
+        self.scheduler.changed.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: u64) -> UserId {
+        UserId::new(id)
+    }
+
+    #[test]
+    fn rejects_requests_when_the_queue_is_full() {
+        let scheduler = Arc::new(Scheduler::default());
+        let tickets: Vec<Ticket> = (1..=MAX_QUEUE_LEN as u64 + 1)
+            .map(|id| scheduler.enqueue(user(id)).unwrap())
+            .collect();
+        assert_eq!(scheduler.pending(), MAX_QUEUE_LEN + 1);
+
+        let full = scheduler.enqueue(user(100));
+        assert!(matches!(full, Err(ScheduleError::QueueFull)));
+
+        drop(tickets);
+        assert_eq!(scheduler.pending(), 0);
+        assert!(scheduler.enqueue(user(100)).is_ok());
+    }
+
+    #[test]
+    fn takes_turns_across_users() {
+        let scheduler = Arc::new(Scheduler::default());
+        let alice = scheduler.enqueue(user(1)).unwrap();
+        let bob = scheduler.enqueue(user(2)).unwrap();
+        let carol = scheduler.enqueue(user(3)).unwrap();
+
+        // One request per user, a second one has to wait for the first
+        assert!(matches!(
+            scheduler.enqueue(user(1)),
+            Err(ScheduleError::AlreadyQueued)
+        ));
+        assert_eq!(
+            (alice.position(), bob.position(), carol.position()),
+            (0, 1, 2)
+        );
+
+        // Alice's next request goes behind everyone who was already waiting
+        drop(alice);
+        let alice = scheduler.enqueue(user(1)).unwrap();
+        assert_eq!(
+            (bob.position(), carol.position(), alice.position()),
+            (0, 1, 2)
+        );
+    }
+
+    #[test]
+    fn rate_limits_a_user() {
+        let scheduler = Arc::new(Scheduler::default());
+        for _ in 0..RATE_LIMIT_REQUESTS {
+            drop(scheduler.enqueue(user(1)).unwrap());
+        }
+
+        match scheduler.enqueue(user(1)) {
+            Err(ScheduleError::RateLimited(wait)) => assert!(wait <= RATE_LIMIT_WINDOW),
+            _ => panic!("expected the user to be rate limited"),
+        }
+        assert!(scheduler.enqueue(user(2)).is_ok());
+    }
+
+    #[test]
+    fn cancel_user_only_cancels_their_requests() {
+        let scheduler = Arc::new(Scheduler::default());
+        let alice = scheduler.enqueue(user(1)).unwrap();
+        let bob = scheduler.enqueue(user(2)).unwrap();
+
+        assert_eq!(scheduler.cancel_user(user(1)), 1);
+        assert!(alice.cancel.is_cancelled());
+        assert!(!bob.cancel.is_cancelled());
+
+        assert_eq!(scheduler.cancel_user(user(3)), 0);
+    }
+
+    #[test]
+    fn cancel_message_checks_the_asker() {
+        let scheduler = Arc::new(Scheduler::default());
+        let ticket = scheduler.enqueue(user(1)).unwrap();
+        ticket.set_message(MessageId::new(10));
+
+        assert!(!scheduler.cancel_message(MessageId::new(10), user(2)));
+        assert!(!ticket.cancel.is_cancelled());
+        assert!(scheduler.cancel_message(MessageId::new(10), user(1)));
+        assert!(ticket.cancel.is_cancelled());
+    }
+}
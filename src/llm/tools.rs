@@ -0,0 +1,328 @@
+use crate::Data;
+use crate::commands::music::play::get_ytdlp_args;
+use crate::commands::utils::to_time;
+use crate::player::{GuildPlayer, is_dj, track_metadata};
+use mistralrs::{Function, Tool, ToolCallResponse, ToolType};
+use poise::serenity_prelude as serenity;
+use serde_json::{Value, json};
+use serenity::Context;
+use serenity::model::prelude::*;
+use songbird::input::{Compose, YoutubeDl};
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use tracing::info;
+
+/// Upper bound of tool call rounds per request, so a confused model can't loop forever.
+pub const MAX_TOOL_ROUNDS: usize = 4;
+
+/// Songs listed by `list_queue`, the rest is only counted.
+const MAX_LISTED_TRACKS: usize = 10;
+
+/// What a tool needs from the invoking user before it is allowed to run.
+#[derive(Clone, Copy, PartialEq)]
+enum Access {
+    /// Only reads state, any guild member may use it.
+    Read,
+    /// Adds to the queue, user needs to be in a voice channel they can speak in.
+    /// Once music is queued the same rules as for `/play` apply.
+    Enqueue,
+    /// Changes playback, user needs to be in the same voice channel as the bot unless they are a DJ.
+    Control,
+}
+
+fn access_for(tool: &str) -> Option<Access> {
+    match tool {
+        "enqueue" => Some(Access::Enqueue),
+        "skip" | "pause" | "resume" => Some(Access::Control),
+        "list_queue" | "now_playing" => Some(Access::Read),
+        _ => None,
+    }
+}
+
+fn function(name: &str, description: &str, parameters: Value) -> Tool {
+    let parameters: HashMap<String, Value> =
+        serde_json::from_value(parameters).expect("Tool parameters must be a JSON object");
+
+    Tool {
+        tp: ToolType::Function,
+        function: Function {
+            description: Some(description.to_string()),
+            name: name.to_string(),
+            parameters: Some(parameters),
+        },
+    }
+}
+
+fn no_parameters() -> Value {
+    json!({ "type": "object", "properties": {} })
+}
+
+/// The music tools offered to the model.
+pub fn definitions() -> Vec<Tool> {
+    vec![
+        function(
+            "enqueue",
+            "Adds a song to the music queue and joins the user's voice channel if needed.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {
+                        "type": "string",
+                        "description": "A YouTube url or a search term, e.g. \"lofi hip hop\"."
+                    }
+                },
+                "required": ["query"],
+            }),
+        ),
+        function("skip", "Skips the currently playing song.", no_parameters()),
+        function(
+            "pause",
+            "Pauses the currently playing song.",
+            no_parameters(),
+        ),
+        function("resume", "Resumes the paused song.", no_parameters()),
+        function(
+            "list_queue",
+            "Lists the songs currently in the queue.",
+            no_parameters(),
+        ),
+        function(
+            "now_playing",
+            "Shows the title, position and status of the current song.",
+            no_parameters(),
+        ),
+    ]
+}
+
+/// Runs a tool call on behalf of `user_id` and returns the result that is handed back to the model.
+/// Failures are reported as text as well, so the model can explain them to the user.
+pub async fn execute(
+    ctx: &Context,
+    data: &Data,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    user_id: UserId,
+    call: &ToolCallResponse,
+) -> String {
+    let name = call.function.name.as_str();
+    info!("LLM tool call `{}` with {}", name, call.function.arguments);
+
+    let query = serde_json::from_str::<Value>(&call.function.arguments)
+        .ok()
+        .and_then(|args| {
+            args.get("query")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+
+    run(ctx, data, guild_id, channel_id, user_id, name, query).await
+}
+
+/// Whether the result of a tool call reports a failure.
+pub fn is_error(result: &str) -> bool {
+    result.starts_with("Error:") || result.starts_with("Permission denied:")
+}
+
+/// Runs the tool `name` with the same access checks as for the model, also used by voice commands.
+/// `channel_id` is the text channel the request came from.
+pub async fn run(
+    ctx: &Context,
+    data: &Data,
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    user_id: UserId,
+    name: &str,
+    query: Option<String>,
+) -> String {
+    let Some(access) = access_for(name) else {
+        return format!("Error: unknown tool `{}`.", name);
+    };
+
+    let Some(guild_id) = guild_id else {
+        return "Error: music tools only work inside a server.".to_string();
+    };
+
+    if let Err(e) = check_access(ctx, data, guild_id, user_id, access).await {
+        return format!("Permission denied: {}", e);
+    }
+
+    if name == "enqueue" {
+        return match query {
+            Some(query) if !query.trim().is_empty() => {
+                enqueue(ctx, data, guild_id, channel_id, user_id, query).await
+            }
+            _ => "Error: `query` is required.".to_string(),
+        };
+    }
+
+    let Some(player) = GuildPlayer::get(ctx, guild_id).await else {
+        return "Error: not in a voice channel.".to_string();
+    };
+    let queue = match player.queue().await {
+        Ok(queue) => queue,
+        Err(e) => return format!("Error: {}", e),
+    };
+
+    match name {
+        "skip" => match queue.skip() {
+            Ok(_) => "Skipped the current song.".to_string(),
+            Err(e) => format!("Error: failed to skip: {}", e),
+        },
+        "pause" => match queue.pause() {
+            Ok(_) => "Paused.".to_string(),
+            Err(e) => format!("Error: failed to pause: {}", e),
+        },
+        "resume" => match queue.resume() {
+            Ok(_) => "Resumed.".to_string(),
+            Err(e) => format!("Error: failed to resume: {}", e),
+        },
+        "list_queue" => {
+            let tracks = queue.current_queue();
+            if tracks.is_empty() {
+                return "The queue is empty.".to_string();
+            }
+
+            let mut lines = vec![format!("{} song(s) in the queue:", tracks.len())];
+            for (i, track) in tracks.iter().take(MAX_LISTED_TRACKS).enumerate() {
+                lines.push(format!("{}. {}", i + 1, describe(track).await));
+            }
+            if tracks.len() > MAX_LISTED_TRACKS {
+                lines.push(format!(
+                    "... and {} more.",
+                    tracks.len() - MAX_LISTED_TRACKS
+                ));
+            }
+            lines.join("\n")
+        }
+        "now_playing" => match queue.current() {
+            Some(current) => match current.get_info().await {
+                Ok(info) => format!(
+                    "Playing {}, for {}, status {:?}.",
+                    describe(&current).await,
+                    to_time(info.position.as_secs()),
+                    info.playing
+                ),
+                Err(e) => format!("Error: failed to read track state: {}", e),
+            },
+            None => "Nothing is playing right now.".to_string(),
+        },
+        _ => unreachable!("access_for only knows the tools handled above"),
+    }
+}
+
+/// Title, artist and duration of a queued track.
+async fn describe(track: &TrackHandle) -> String {
+    let metadata = track_metadata(track).await.unwrap_or_default();
+    let mut description = format!(
+        "{} - {}",
+        metadata.title.as_deref().unwrap_or("Unknown Title"),
+        metadata.artist.as_deref().unwrap_or("Unknown Artist"),
+    );
+    if let Some(duration) = metadata.duration {
+        description.push_str(&format!(" ({})", to_time(duration.as_secs())));
+    }
+    description
+}
+
+async fn check_access(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+    access: Access,
+) -> Result<(), &'static str> {
+    if access == Access::Read {
+        return Ok(());
+    }
+
+    let bot_channel = match GuildPlayer::get(ctx, guild_id).await {
+        Some(player) => player.channel().await.unwrap_or(None),
+        None => None,
+    };
+    // DJs and admins may control the music from anywhere, like with the commands
+    if access == Access::Control {
+        if let Some(bot_channel) = bot_channel {
+            if is_dj(ctx, data, guild_id, user_id, bot_channel).await {
+                return Ok(());
+            }
+        }
+    }
+
+    let member = guild_id
+        .member(ctx, user_id)
+        .await
+        .map_err(|_| "could not look up the user in this server")?;
+
+    let (user_channel, permissions) = {
+        let guild = ctx.cache.guild(guild_id).ok_or("server is not cached")?;
+        let user_channel = guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|voice_state| voice_state.channel_id)
+            .ok_or("the user is not in a voice channel")?;
+        let channel = guild
+            .channels
+            .get(&user_channel)
+            .ok_or("the user's voice channel is not cached")?;
+        (user_channel, guild.user_permissions_in(channel, &member))
+    };
+
+    if !permissions.connect() || !permissions.speak() {
+        return Err("the user may not connect and speak in their voice channel");
+    }
+
+    if access == Access::Control && bot_channel != Some(user_channel) {
+        return Err("the user is not in the bot's voice channel");
+    }
+
+    Ok(())
+}
+
+async fn enqueue(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    user_id: UserId,
+    query: String,
+) -> String {
+    let player = match GuildPlayer::join_to_enqueue(ctx, data, guild_id, user_id).await {
+        Ok(player) => player,
+        Err(e) => return format!("Error: {}", e),
+    };
+
+    let http_client = data.http_client.clone();
+    let mut source = if query.starts_with("http") {
+        YoutubeDl::new(http_client, query.replace("music.", ""))
+    } else {
+        YoutubeDl::new_search(http_client, query.clone())
+    }
+    .user_args(get_ytdlp_args());
+
+    let metadata = match source.aux_metadata().await {
+        Ok(meta) => meta,
+        Err(e) => {
+            info!("LLM enqueue failed for '{}': {:?}", query, e);
+            return format!("Error: could not find anything for `{}`.", query);
+        }
+    };
+
+    let queued = match player
+        .enqueue(source.into(), metadata.clone(), channel_id)
+        .await
+    {
+        Ok(queued) => queued,
+        Err(e) => return format!("Error: {}", e),
+    };
+
+    format!(
+        "Added {} - {} to the queue, {} song(s) queued.",
+        metadata
+            .title
+            .unwrap_or_else(|| "Unknown Title".to_string()),
+        metadata
+            .artist
+            .unwrap_or_else(|| "Unknown Artist".to_string()),
+        queued
+    )
+}
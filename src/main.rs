@@ -1,29 +1,59 @@
-#[macro_use]
-mod macros;
+mod announcer;
+mod autoplay;
 mod commands;
+mod error;
 mod events;
+mod llm;
+mod player;
+mod receive;
+mod recording;
+mod settings;
+mod soundboard;
+mod stt;
+mod triggers;
+mod tts;
 
-use poise::{FrameworkError, serenity_prelude as serenity};
+use poise::{CreateReply, FrameworkError, serenity_prelude as serenity};
 use serenity::all::ActivityData;
 use serenity::{Client, GatewayIntents};
 use songbird::SerenityInit;
 use std::env;
 use std::process::ExitCode;
 use std::sync::Arc;
-use tokio::sync::{Mutex, mpsc};
 
+use crate::commands::ui;
 use crate::events::HandleEvent;
+use crate::llm::lifecycle::ModelManager;
+use crate::recording::Recorder;
+use crate::settings::SettingsStore;
+use crate::stt::listener::Listener;
+use crate::triggers::Triggers;
+use crate::tts::queue::SpeechQueue;
 
-type Error = serenity::Error;
+type Error = error::BotError;
 type Context<'a> = poise::Context<'a, Data, Error>;
 
 const LLM_TIMEOUT_SEC: u64 = 600;
 
+#[derive(Clone)]
 pub struct Data {
     http_client: reqwest::Client,
     restart_requested: tokio_util::sync::CancellationToken,
-    llm_model: Arc<Mutex<Option<mistralrs::Model>>>,
-    llm_activity_tx: mpsc::UnboundedSender<()>,
+    llm: Arc<ModelManager>,
+    llm_scheduler: Arc<llm::scheduler::Scheduler>,
+    settings: Arc<SettingsStore>,
+    speech_queue: Arc<SpeechQueue>,
+    triggers: Arc<Triggers>,
+    listener: Arc<Listener>,
+    recorder: Arc<Recorder>,
+}
+
+/// Shows `error` to the user who ran the command.
+async fn report_error(ctx: Context<'_>, error: &Error) {
+    let reply = CreateReply::default().embed(ui::theme(ctx).await.error(error));
+    if let Err(e) = ctx.send(reply).await {
+        println!("Error while reporting error: {}", e)
+    }
 }
 
 async fn on_error(error: FrameworkError<'_, Data, Error>) {
@@ -34,6 +64,32 @@ async fn on_error(error: FrameworkError<'_, Data, Error>) {
         FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         FrameworkError::Command { error, ctx, .. } => {
             println!("Error in command `{}`: {:?}", ctx.command().name, error,);
+            // LLM failures are shown in place of the answer already
+            if !matches!(error, Error::Llm(_)) {
+                report_error(ctx, &error).await;
+            }
+        }
+        FrameworkError::MissingUserPermissions {
+            missing_permissions,
+            ctx,
+            ..
+        } => {
+            let error = Error::Permission(match missing_permissions {
+                Some(permissions) => format!("You need the {} permission for this.", permissions),
+                None => "Couldn't check your permissions.".to_string(),
+            });
+            report_error(ctx, &error).await;
+        }
+        FrameworkError::MissingBotPermissions {
+            missing_permissions,
+            ctx,
+            ..
+        } => {
+            let error = Error::Permission(format!(
+                "I need the {} permission for this.",
+                missing_permissions
+            ));
+            report_error(ctx, &error).await;
         }
         error => {
             if let Err(e) = poise::builtins::on_error(error).await {
@@ -59,19 +115,37 @@ async fn main() -> ExitCode {
 
     let options: poise::FrameworkOptions<Data, Error> = poise::FrameworkOptions {
         commands: vec![
+            commands::ask::ask(),
+            commands::ask::chat(),
+            commands::ask::cancel(),
+            commands::converse::converse(),
             commands::help::help(),
+            commands::llm::llm(),
             commands::restart::restart(),
+            commands::tts::tts(),
+            commands::theme::theme(),
+            commands::trigger::trigger(),
+            commands::voice::voice(),
+            commands::music::announce::announce(),
+            commands::music::autoplay::autoplay(),
             commands::music::clear::clear(),
+            commands::music::dj::dj(),
             commands::music::join::join(),
+            commands::music::listen::listen(),
+            commands::music::move_here::move_here(),
             commands::music::nowplaying::nowplaying(),
             commands::music::pause::pause(),
             commands::music::play::play(),
             commands::music::queue::queue(),
+            commands::music::record::record(),
+            commands::music::record::clip(),
             commands::music::resume::resume(),
             commands::music::shuffle::shuffle(),
             commands::music::skip::skip(),
             commands::music::stop::stop(),
             commands::music::say::say(),
+            commands::music::say::say_skip(),
+            commands::music::sound::sound(),
         ],
         prefix_options: poise::PrefixFrameworkOptions {
             prefix: Some(env::var("PREFIX").unwrap_or_else(|_| "!".to_string())),
@@ -116,44 +190,12 @@ async fn main() -> ExitCode {
                 println!("Logged in as {}", _ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
 
-                let llm_model = Arc::new(Mutex::new(None));
-                let (llm_activity_tx, mut llm_activity_rx) = mpsc::unbounded_channel();
-                let llm_idle_timeout = std::time::Duration::from_secs(LLM_TIMEOUT_SEC);
-
-                tokio::spawn({
-                    let llm_model = llm_model.clone();
-                    async move {
-                        let idle_sleep = tokio::time::sleep(llm_idle_timeout);
-                        tokio::pin!(idle_sleep);
-
-                        loop {
-                            tokio::select! {
-                                _ = &mut idle_sleep => {
-                                    if let Ok(mut guard) = llm_model.try_lock() {
-                                        if guard.is_some() {
-                                            *guard = None;
-                                            println!(
-                                                "Dropped LLM model after {:?} of inactivity",
-                                                llm_idle_timeout
-                                            );
-                                        }
-                                    }
-                                    idle_sleep
-                                        .as_mut()
-                                        .reset(tokio::time::Instant::now() + llm_idle_timeout);
-                                }
-                                msg = llm_activity_rx.recv() => {
-                                    if msg.is_none() {
-                                        break;
-                                    }
-                                    idle_sleep
-                                        .as_mut()
-                                        .reset(tokio::time::Instant::now() + llm_idle_timeout);
-                                }
-                            }
-                        }
-                    }
-                });
+                let settings = Arc::new(SettingsStore::load().await);
+
+                let llm = ModelManager::start(std::time::Duration::from_secs(LLM_TIMEOUT_SEC));
+                if env::var("LLM_PRELOAD").is_ok_and(|value| value == "true" || value == "1") {
+                    llm.load();
+                }
 
                 Ok(Data {
                     http_client: reqwest::Client::builder()
@@ -177,8 +219,13 @@ async fn main() -> ExitCode {
                             panic!("Failed to create http client")
                         }),
                         restart_requested: restart_requested_token_clone,
-                        llm_model: llm_model,
-                        llm_activity_tx,
+                        llm,
+                        llm_scheduler: Arc::new(llm::scheduler::Scheduler::default()),
+                        speech_queue: Arc::new(SpeechQueue::new(settings.clone())),
+                        settings,
+                        triggers: Arc::new(Triggers::default()),
+                        listener: Arc::new(Listener::default()),
+                        recorder: Arc::new(Recorder::default()),
                 })
             })
         })
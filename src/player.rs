@@ -0,0 +1,439 @@
+//! The bot's connection to a guild's voice channel. Commands get a `GuildPlayer` instead of
+//! talking to songbird, so joining, the channel checks and timeouts work the same everywhere.
+
+use crate::announcer::Announcer;
+use crate::autoplay::Autoplay;
+use crate::commands::music::eventhandller::CustomSongbirdEventHandler;
+use crate::{Context, Data, Error, receive};
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId, UserId};
+use serenity::prelude::TypeMapKey;
+use songbird::input::{AuxMetadata, Input};
+use songbird::tracks::{TrackHandle, TrackQueue};
+use songbird::{Call, CoreEvent, Event, Songbird, TrackEvent};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, LazyLock, Weak};
+use std::time::Duration;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Voice operations give up after this long instead of hanging the command.
+const VOICE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The call the event handlers are registered on. Joins hold its lock, so concurrent commands
+/// don't both join and register the handlers twice.
+type HandledCall = Arc<Mutex<Weak<Mutex<Call>>>>;
+
+static HANDLED_CALLS: LazyLock<std::sync::Mutex<HashMap<GuildId, HandledCall>>> =
+    LazyLock::new(Default::default);
+
+/// A track of the queue, kept in its typemap.
+#[derive(Clone)]
+pub struct QueuedTrack {
+    pub metadata: AuxMetadata,
+    /// Text channel the track was requested in.
+    pub channel_id: ChannelId,
+    /// How often the track was restarted after its stream failed.
+    pub retries: u32,
+}
+
+impl TypeMapKey for QueuedTrack {
+    type Value = QueuedTrack;
+}
+
+/// `None` for speech and sounds, they aren't part of the queue.
+pub async fn queued_track(track: &TrackHandle) -> Option<QueuedTrack> {
+    track.typemap().read().await.get::<QueuedTrack>().cloned()
+}
+
+pub async fn track_metadata(track: &TrackHandle) -> Option<AuxMetadata> {
+    queued_track(track).await.map(|queued| queued.metadata)
+}
+
+async fn push(call: &mut Call, input: Input, queued: QueuedTrack) -> TrackHandle {
+    let track = call.enqueue_input(input).await;
+    track.typemap().write().await.insert::<QueuedTrack>(queued);
+    track
+}
+
+async fn timeout<T>(future: impl Future<Output = T>, error: &'static str) -> Result<T, Error> {
+    tokio::time::timeout(VOICE_TIMEOUT, future)
+        .await
+        .map_err(|_| Error::Voice(error))
+}
+
+async fn manager(ctx: &serenity::Context) -> Arc<Songbird> {
+    songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone()
+}
+
+/// The voice channel `user_id` is in.
+pub fn user_channel(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Option<ChannelId> {
+    ctx.cache.guild(guild_id).and_then(|guild| {
+        guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|voice_state| voice_state.channel_id)
+    })
+}
+
+/// How many others besides the bot are in `channel_id`.
+pub fn listeners(ctx: &serenity::Context, guild_id: GuildId, channel_id: ChannelId) -> usize {
+    let bot_id = ctx.cache.current_user().id;
+    ctx.cache.guild(guild_id).map_or(0, |guild| {
+        guild
+            .voice_states
+            .values()
+            .filter(|voice_state| {
+                voice_state.channel_id == Some(channel_id) && voice_state.user_id != bot_id
+            })
+            .count()
+    })
+}
+
+/// DJs and admins may control the music without being in the bot's voice channel.
+pub async fn is_dj(
+    ctx: &serenity::Context,
+    data: &Data,
+    guild_id: GuildId,
+    user_id: UserId,
+    channel_id: ChannelId,
+) -> bool {
+    let Ok(member) = guild_id.member(ctx, user_id).await else {
+        return false;
+    };
+
+    let dj_role = data.settings.guild(guild_id).await.dj_role;
+    if dj_role.is_some_and(|role| member.roles.contains(&role)) {
+        return true;
+    }
+
+    ctx.cache
+        .guild(guild_id)
+        .and_then(|guild| {
+            let channel = guild.channels.get(&channel_id)?;
+            Some(guild.user_permissions_in(channel, &member).manage_guild())
+        })
+        .unwrap_or(false)
+}
+
+#[derive(Clone)]
+pub struct GuildPlayer {
+    pub guild_id: GuildId,
+    call: Arc<Mutex<Call>>,
+}
+
+impl GuildPlayer {
+    /// The player if the bot is in a voice channel of the guild.
+    pub async fn get(ctx: &serenity::Context, guild_id: GuildId) -> Option<Self> {
+        let call = manager(ctx).await.get(guild_id)?;
+        Some(Self { guild_id, call })
+    }
+
+    /// Joins the voice channel of `user_id` unless the bot is connected already.
+    pub async fn join(
+        ctx: &serenity::Context,
+        data: &Data,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Self, Error> {
+        let handled = HANDLED_CALLS
+            .lock()
+            .unwrap()
+            .entry(guild_id)
+            .or_default()
+            .clone();
+        let mut handled = handled.lock().await;
+
+        if let Some(player) = Self::get(ctx, guild_id).await {
+            return Ok(player);
+        }
+
+        let channel_id = user_channel(ctx, guild_id, user_id)
+            .ok_or(Error::Voice("Join a voice channel first!"))?;
+        let manager = manager(ctx).await;
+        let joined = timeout(
+            manager.join(guild_id, channel_id),
+            "Timed out joining the voice channel.",
+        )
+        .await;
+        let call = match joined {
+            Ok(Ok(call)) => call,
+            Ok(Err(e)) => {
+                println!("Failed to join voice channel: {}", e);
+                // Otherwise the half-joined call is taken for a working one next time
+                let _ = manager.remove(guild_id).await;
+                return Err(Error::Voice(
+                    "Error joining channel. Please ensure I have the correct permissions.",
+                ));
+            }
+            Err(e) => {
+                let _ = manager.remove(guild_id).await;
+                return Err(e);
+            }
+        };
+
+        let player = Self { guild_id, call };
+        let mut call = player.lock().await?;
+        // Stay able to hear when rejoining while listening or recording
+        let _ = call.deafen(!receive::wanted(data, guild_id)).await;
+
+        // Registered once per call, they stay across reconnects and channel moves
+        if !handled.ptr_eq(&Arc::downgrade(&player.call)) {
+            *handled = Arc::downgrade(&player.call);
+
+            let events = CustomSongbirdEventHandler::new(ctx.clone(), data.clone(), guild_id);
+            for event in [
+                Event::Core(CoreEvent::DriverConnect),
+                Event::Core(CoreEvent::DriverReconnect),
+                Event::Core(CoreEvent::DriverDisconnect),
+                Event::Track(TrackEvent::Error),
+            ] {
+                call.add_global_event(event, events.clone());
+            }
+            let announcer = Announcer::new(ctx.clone(), data.clone(), guild_id);
+            for event in [TrackEvent::Play, TrackEvent::End] {
+                call.add_global_event(Event::Track(event), announcer.clone());
+            }
+            let autoplay = Autoplay::new(ctx.clone(), data.clone(), guild_id);
+            for event in [TrackEvent::Play, TrackEvent::End] {
+                call.add_global_event(Event::Track(event), autoplay.clone());
+            }
+        }
+        drop(call);
+
+        Ok(player)
+    }
+
+    /// Joins `user_id` to add music. While nothing is queued the bot follows them to their channel,
+    /// as long as nobody else listens or they are a DJ, like with `/move-here`.
+    /// Otherwise adding music is a control like any other.
+    pub async fn join_to_enqueue(
+        ctx: &serenity::Context,
+        data: &Data,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Self, Error> {
+        let player = Self::join(ctx, data, guild_id, user_id).await?;
+        let Some(bot_channel) = player.channel().await? else {
+            return Ok(player);
+        };
+        let user_channel = user_channel(ctx, guild_id, user_id);
+        if user_channel == Some(bot_channel) {
+            return Ok(player);
+        }
+
+        let dj = is_dj(ctx, data, guild_id, user_id, bot_channel).await;
+        match user_channel {
+            Some(channel_id) if player.queue().await?.is_empty() => {
+                if listeners(ctx, guild_id, bot_channel) > 0 && !dj {
+                    return Err(Error::Voice(
+                        "Others are still listening in my voice channel, join it or ask a DJ to use `/move-here`.",
+                    ));
+                }
+                player.move_to(ctx, channel_id).await?;
+            }
+            _ => {
+                if !dj {
+                    return Err(Error::Voice("Join my voice channel first!"));
+                }
+            }
+        }
+        Ok(player)
+    }
+
+    /// The player if the bot is in the same voice channel as `user_id`.
+    pub async fn shared_with(
+        ctx: &serenity::Context,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Option<Self> {
+        let user_channel = user_channel(ctx, guild_id, user_id)?;
+        let player = Self::get(ctx, guild_id).await?;
+        (player.channel().await.ok()? == Some(user_channel)).then_some(player)
+    }
+
+    /// For commands that need the bot in voice.
+    pub async fn connected(ctx: Context<'_>) -> Result<Self, Error> {
+        let guild_id = ctx.guild_id().ok_or(Error::Voice("Not in a server."))?;
+        Self::get(ctx.serenity_context(), guild_id)
+            .await
+            .ok_or(Error::Voice("Not in a voice channel."))
+    }
+
+    /// For commands that bring the bot into the author's voice channel.
+    pub async fn ensure_connected(ctx: Context<'_>) -> Result<Self, Error> {
+        let guild_id = ctx.guild_id().ok_or(Error::Voice("Not in a server."))?;
+        Self::join(
+            ctx.serenity_context(),
+            ctx.data(),
+            guild_id,
+            ctx.author().id,
+        )
+        .await
+    }
+
+    /// For commands the author may only use while listening in the bot's voice channel.
+    pub async fn require_same_channel(ctx: Context<'_>) -> Result<Self, Error> {
+        let player = Self::connected(ctx).await?;
+        let user_channel = user_channel(ctx.serenity_context(), player.guild_id, ctx.author().id);
+        if user_channel.is_none() || player.channel().await? != user_channel {
+            return Err(Error::Voice("Join my voice channel first!"));
+        }
+        Ok(player)
+    }
+
+    /// For music controls, DJs and admins may use them from anywhere.
+    pub async fn require_controller(ctx: Context<'_>) -> Result<Self, Error> {
+        let player = Self::connected(ctx).await?;
+        let Some(bot_channel) = player.channel().await? else {
+            return Ok(player);
+        };
+
+        let user_channel = user_channel(ctx.serenity_context(), player.guild_id, ctx.author().id);
+        if user_channel != Some(bot_channel)
+            && !is_dj(
+                ctx.serenity_context(),
+                ctx.data(),
+                player.guild_id,
+                ctx.author().id,
+                bot_channel,
+            )
+            .await
+        {
+            return Err(Error::Voice("Join my voice channel first!"));
+        }
+        Ok(player)
+    }
+
+    pub async fn lock(&self) -> Result<MutexGuard<'_, Call>, Error> {
+        timeout(
+            self.call.lock(),
+            "Timed out trying to acquire the voice handler.",
+        )
+        .await
+    }
+
+    /// The call, for things that hold on to it like the speech queue.
+    pub fn call(&self) -> Arc<Mutex<Call>> {
+        self.call.clone()
+    }
+
+    /// The voice channel the bot is in.
+    pub async fn channel(&self) -> Result<Option<ChannelId>, Error> {
+        let channel = self.lock().await?.current_channel();
+        Ok(channel.map(|channel| ChannelId::new(channel.0.get())))
+    }
+
+    pub async fn queue(&self) -> Result<TrackQueue, Error> {
+        Ok(self.lock().await?.queue().clone())
+    }
+
+    /// Queues `input` requested in `channel_id`, returns the length of the queue afterwards.
+    pub async fn enqueue(
+        &self,
+        input: Input,
+        metadata: AuxMetadata,
+        channel_id: ChannelId,
+    ) -> Result<usize, Error> {
+        let mut call = self.lock().await?;
+        let queued = QueuedTrack {
+            metadata,
+            channel_id,
+            retries: 0,
+        };
+        push(&mut call, input, queued).await;
+        Ok(call.queue().len())
+    }
+
+    /// Plays `input` in place of the `failed` track, from `position` if given.
+    pub async fn restart(
+        &self,
+        input: Input,
+        queued: QueuedTrack,
+        failed: &TrackHandle,
+        position: Option<Duration>,
+    ) -> Result<(), Error> {
+        let mut call = self.lock().await?;
+        let track = push(&mut call, input, queued).await;
+        let queue = call.queue().clone();
+        drop(call);
+
+        // The queue may or may not have moved on from the failed track yet
+        let mut interrupted = None;
+        queue.modify_queue(|tracks| {
+            let Some(index) = tracks
+                .iter()
+                .position(|queued| queued.uuid() == track.uuid())
+            else {
+                return;
+            };
+            let Some(restarted) = tracks.remove(index) else {
+                return;
+            };
+            match tracks.front().map(|front| (front.uuid(), front.handle())) {
+                // Played next once the queue drops the failed track
+                Some((uuid, _)) if uuid == failed.uuid() => tracks.insert(1, restarted),
+                Some((_, next)) => {
+                    interrupted = Some(next);
+                    tracks.push_front(restarted);
+                }
+                None => tracks.push_front(restarted),
+            }
+        });
+
+        if let Some(next) = interrupted {
+            let _ = next.pause();
+            let _ = track.play();
+        }
+        if let Some(position) = position {
+            let _ = track.seek(position);
+        }
+        Ok(())
+    }
+
+    /// Moves the bot to `channel_id`, the queue keeps playing there.
+    pub async fn move_to(
+        &self,
+        ctx: &serenity::Context,
+        channel_id: ChannelId,
+    ) -> Result<(), Error> {
+        timeout(
+            manager(ctx).await.join(self.guild_id, channel_id),
+            "Timed out moving to the voice channel.",
+        )
+        .await?
+        .map_err(|e| {
+            println!("Failed to move to voice channel: {}", e);
+            Error::Voice("Error moving channel. Please ensure I have the correct permissions.")
+        })?;
+        Ok(())
+    }
+
+    /// Stops the music and leaves the voice channel.
+    pub async fn leave(self, ctx: &serenity::Context, data: &Data) -> Result<(), Error> {
+        self.queue().await?.stop();
+        // Nothing would be heard anymore, the recording is uploaded as far as it got
+        data.recorder.stop(self.guild_id);
+        data.listener
+            .stop(data, self.guild_id, Some(self.call()))
+            .await;
+
+        let manager = manager(ctx).await;
+        timeout(
+            manager.remove(self.guild_id),
+            "Timed out leaving the voice channel.",
+        )
+        .await?
+        .map_err(|e| {
+            println!("Failed to leave voice channel: {}", e);
+            Error::Voice("Failed to leave voice channel.")
+        })
+    }
+}
@@ -0,0 +1,34 @@
+//! Receiving voice is shared by listening and recording, the bot only hears while one of them runs.
+
+use crate::Data;
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use songbird::driver::{Channels, DecodeConfig, DecodeMode, SampleRate};
+use songbird::{Call, Config};
+
+/// Received voice is decoded to mono at this rate.
+pub const SAMPLE_RATE: usize = 48000;
+/// Samples songbird hands out per speaker every 20 ms.
+pub const TICK_SAMPLES: usize = SAMPLE_RATE / 50;
+
+/// Whether anything running in the guild needs to hear voice.
+pub fn wanted(data: &Data, guild_id: GuildId) -> bool {
+    data.listener.is_listening(guild_id) || data.recorder.is_recording(guild_id)
+}
+
+/// Undeafens and decodes received voice while it is wanted, deafens again otherwise.
+/// Call after starting or stopping anything that receives voice.
+pub async fn update(data: &Data, guild_id: GuildId, call: &mut Call) -> Result<(), &'static str> {
+    if wanted(data, guild_id) {
+        call.set_config(
+            Config::default().decode_mode(DecodeMode::Decode(DecodeConfig::new(
+                Channels::Mono,
+                SampleRate::Hz48000,
+            ))),
+        );
+        call.deafen(false).await.map_err(|_| "Failed to undeafen.")
+    } else {
+        call.set_config(Config::default());
+        call.deafen(true).await.map_err(|_| "Failed to deafen.")
+    }
+}
@@ -0,0 +1,387 @@
+//! Recording of voice channels: everyone is mixed into one file and also kept on a track of their
+//! own, both encoded to Ogg Opus while the audio arrives. The last minute of the mix is kept around
+//! for clips.
+
+pub mod ogg;
+
+use crate::Data;
+use crate::commands::ui::Theme;
+use crate::receive::{self, TICK_SAMPLES};
+use ogg::{FRAME_SAMPLES, OpusWriter};
+use poise::serenity_prelude as serenity;
+use serenity::Context;
+use serenity::all::{
+    ChannelId, CreateAttachment, CreateMessage, EditMessage, GuildId, MessageId, UserId,
+};
+use songbird::events::context_data::{VoiceData, VoiceTick};
+use songbird::{Call, CoreEvent, Event, EventContext, EventHandler};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Recordings stop once their files would get too large to upload.
+const MAX_RECORDING_BYTES: usize = 8 * 1024 * 1024;
+const MAX_DURATION: Duration = Duration::from_secs(60 * 60);
+pub const MAX_CLIP_SECS: usize = 60;
+const MAX_CLIP_SAMPLES: usize = MAX_CLIP_SECS * receive::SAMPLE_RATE;
+/// Discord takes at most this many files per message.
+const MAX_ATTACHMENTS: usize = 10;
+
+/// The files of a recording as they are being written.
+struct Tracks {
+    users: HashMap<u32, UserId>,
+    mix: OpusWriter,
+    /// One track per SSRC, started when its speaker first talked.
+    speakers: HashMap<u32, OpusWriter>,
+    /// A silent frame, used while a speaker doesn't talk.
+    silence: Vec<u8>,
+    ticks: u64,
+    /// The latest audio of the mix for `/clip`.
+    clip: VecDeque<i16>,
+    truncated: bool,
+}
+
+impl Tracks {
+    fn new() -> Result<Self, String> {
+        let silence = OpusWriter::new(0)?.encode(&[0; FRAME_SAMPLES])?;
+
+        Ok(Self {
+            users: HashMap::new(),
+            mix: OpusWriter::new(0)?,
+            speakers: HashMap::new(),
+            silence,
+            ticks: 0,
+            clip: VecDeque::with_capacity(MAX_CLIP_SAMPLES),
+            truncated: false,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.mix.size() + self.speakers.values().map(OpusWriter::size).sum::<usize>()
+    }
+
+    fn tick(&mut self, tick: &VoiceTick) -> Result<(), String> {
+        let mut mix = [0i32; TICK_SAMPLES];
+
+        for (ssrc, voice) in &tick.speaking {
+            let Some(decoded) = decoded(voice) else {
+                continue;
+            };
+            for (mixed, sample) in mix.iter_mut().zip(decoded) {
+                *mixed += *sample as i32;
+            }
+
+            if !self.speakers.contains_key(ssrc) {
+                // Late joiners are padded so all tracks line up with the mix
+                let mut track = OpusWriter::new(self.speakers.len() as u32 + 1)?;
+                for _ in 0..self.ticks {
+                    track.push_packet(self.silence.clone());
+                }
+                self.speakers.insert(*ssrc, track);
+            }
+            if let Some(track) = self.speakers.get_mut(ssrc) {
+                track.push(decoded)?;
+            }
+        }
+
+        // Also for speakers whose audio was skipped above, every track gets a frame per tick
+        for (ssrc, track) in &mut self.speakers {
+            if tick.speaking.get(ssrc).and_then(decoded).is_none() {
+                track.push_packet(self.silence.clone());
+            }
+        }
+
+        let mix: Vec<i16> = mix
+            .iter()
+            .map(|sample| (*sample).clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+            .collect();
+        self.mix.push(&mix)?;
+        if self.clip.len() + mix.len() > MAX_CLIP_SAMPLES {
+            self.clip
+                .drain(..self.clip.len() + mix.len() - MAX_CLIP_SAMPLES);
+        }
+        self.clip.extend(mix);
+
+        self.ticks += 1;
+        Ok(())
+    }
+}
+
+/// A running recording, the notice in the text channel tells everyone about it.
+struct Recording {
+    stop: CancellationToken,
+    tracks: Arc<Mutex<Option<Tracks>>>,
+}
+
+/// Guilds the bot is recording in.
+#[derive(Default)]
+pub struct Recorder {
+    recordings: Mutex<HashMap<GuildId, Recording>>,
+}
+
+impl Recorder {
+    pub fn is_recording(&self, guild_id: GuildId) -> bool {
+        self.recordings.lock().unwrap().contains_key(&guild_id)
+    }
+
+    /// Starts recording the call, the files are uploaded to `channel_id` when it ends
+    /// and `notice` is updated.
+    pub async fn start(
+        &self,
+        ctx: &Context,
+        data: &Data,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        notice: MessageId,
+        handler_lock: Arc<tokio::sync::Mutex<Call>>,
+    ) -> Result<(), &'static str> {
+        let stop = CancellationToken::new();
+        let tracks = match Tracks::new() {
+            Ok(tracks) => Arc::new(Mutex::new(Some(tracks))),
+            Err(e) => {
+                println!("Failed to start recording: {}", e);
+                return Err("Failed to start recording.");
+            }
+        };
+        {
+            let mut recordings = self.recordings.lock().unwrap();
+            if recordings.contains_key(&guild_id) {
+                return Err("Already recording.");
+            }
+            recordings.insert(
+                guild_id,
+                Recording {
+                    stop: stop.clone(),
+                    tracks: tracks.clone(),
+                },
+            );
+        }
+
+        let handler = RecordingReceiver {
+            stop: stop.clone(),
+            tracks: tracks.clone(),
+        };
+        {
+            let mut call = handler_lock.lock().await;
+            for event in [CoreEvent::SpeakingStateUpdate, CoreEvent::VoiceTick] {
+                call.add_global_event(event.into(), handler.clone());
+            }
+            if let Err(e) = receive::update(data, guild_id, &mut call).await {
+                self.recordings.lock().unwrap().remove(&guild_id);
+                stop.cancel();
+                return Err(e);
+            }
+        }
+
+        tokio::spawn(finish(
+            ctx.clone(),
+            data.clone(),
+            guild_id,
+            channel_id,
+            notice,
+            handler_lock,
+            stop,
+            tracks,
+        ));
+        info!("Recording in {}", guild_id);
+        Ok(())
+    }
+
+    /// Ends the recording, returns false if there was none.
+    /// The files are uploaded in the background.
+    pub fn stop(&self, guild_id: GuildId) -> bool {
+        match self.recordings.lock().unwrap().get(&guild_id) {
+            Some(recording) if !recording.stop.is_cancelled() => {
+                recording.stop.cancel();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Encodes the last `seconds` of the mix, `None` if the guild isn't being recorded.
+    pub fn clip(&self, guild_id: GuildId, seconds: usize) -> Option<Result<Vec<u8>, String>> {
+        let tracks = self
+            .recordings
+            .lock()
+            .unwrap()
+            .get(&guild_id)?
+            .tracks
+            .clone();
+        let samples: Vec<i16> = {
+            let tracks = tracks.lock().unwrap();
+            let tracks = tracks.as_ref()?;
+            let count = tracks.clip.len().min(seconds * receive::SAMPLE_RATE);
+            tracks
+                .clip
+                .range(tracks.clip.len() - count..)
+                .copied()
+                .collect()
+        };
+
+        Some(encode(&samples))
+    }
+}
+
+/// The audio of a speaker in one tick, only whole ticks line up with the mix.
+fn decoded(voice: &VoiceData) -> Option<&[i16]> {
+    voice
+        .decoded_voice
+        .as_deref()
+        .filter(|decoded| decoded.len() == TICK_SAMPLES)
+}
+
+fn encode(samples: &[i16]) -> Result<Vec<u8>, String> {
+    let mut writer = OpusWriter::new(0)?;
+    for frame in samples.chunks(FRAME_SAMPLES) {
+        let mut padded = [0; FRAME_SAMPLES];
+        padded[..frame.len()].copy_from_slice(frame);
+        writer.push(&padded)?;
+    }
+    Ok(writer.finish())
+}
+
+/// Waits for the recording to end, then uploads its files.
+#[allow(clippy::too_many_arguments)]
+async fn finish(
+    ctx: Context,
+    data: Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    notice: MessageId,
+    handler_lock: Arc<tokio::sync::Mutex<Call>>,
+    stop: CancellationToken,
+    tracks: Arc<Mutex<Option<Tracks>>>,
+) {
+    tokio::select! {
+        _ = stop.cancelled() => {}
+        _ = tokio::time::sleep(MAX_DURATION) => stop.cancel(),
+    }
+
+    data.recorder.recordings.lock().unwrap().remove(&guild_id);
+    // The receivers remove themselves on their next event
+    let _ = receive::update(&data, guild_id, &mut *handler_lock.lock().await).await;
+    info!("Stopped recording in {}", guild_id);
+
+    let Some(tracks) = tracks.lock().unwrap().take() else {
+        return;
+    };
+    let seconds = tracks.ticks / 50;
+    let truncated = tracks.truncated;
+
+    let mut files = vec![CreateAttachment::bytes(
+        tracks.mix.finish(),
+        "recording.ogg",
+    )];
+    let mut names: HashMap<String, usize> = HashMap::new();
+    for (ssrc, track) in tracks.speakers {
+        let name = tracks
+            .users
+            .get(&ssrc)
+            .map(|user_id| {
+                ctx.cache
+                    .user(*user_id)
+                    .map(|user| user.name.clone())
+                    .unwrap_or_else(|| user_id.to_string())
+            })
+            .unwrap_or_else(|| format!("speaker-{}", ssrc));
+        let mut name: String = name
+            .chars()
+            .filter(|ch| ch.is_ascii_alphanumeric() || *ch == '-' || *ch == '_' || *ch == '.')
+            .collect();
+        if name.is_empty() {
+            name = format!("speaker-{}", ssrc);
+        }
+
+        // Rejoining gives the same user a new SSRC
+        let count = names.entry(name.clone()).or_default();
+        *count += 1;
+        let filename = match *count {
+            1 => format!("{}.ogg", name),
+            count => format!("{}-{}.ogg", name, count),
+        };
+        files.push(CreateAttachment::bytes(track.finish(), filename));
+    }
+
+    let description = format!(
+        "Recorded {}:{:02}, the mix and one track per speaker are attached.{}",
+        seconds / 60,
+        seconds % 60,
+        if truncated {
+            "\nThe recording got too large and was stopped early."
+        } else {
+            ""
+        }
+    );
+    let theme = Theme::of(&data.settings, Some(guild_id)).await;
+    let mut chunks = files.chunks(MAX_ATTACHMENTS);
+    if let Some(first) = chunks.next() {
+        let message = CreateMessage::new()
+            .embed(
+                theme
+                    .success(":red_circle: Recording finished")
+                    .description(description),
+            )
+            .add_files(first.to_vec());
+        if let Err(e) = channel_id.send_message(&ctx.http, message).await {
+            println!("Failed to upload recording: {}", e);
+        }
+    }
+    for chunk in chunks {
+        let message = CreateMessage::new().add_files(chunk.to_vec());
+        if let Err(e) = channel_id.send_message(&ctx.http, message).await {
+            println!("Failed to upload recording: {}", e);
+        }
+    }
+
+    let ended = EditMessage::new().embed(
+        theme
+            .success(":white_circle: Recording ended")
+            .description("Nobody in the voice channel is being recorded anymore."),
+    );
+    if let Err(e) = channel_id.edit_message(&ctx.http, notice, ended).await {
+        println!("Failed to update recording notice: {}", e);
+    }
+}
+
+/// Mixes and encodes everything heard in the call.
+#[derive(Clone)]
+struct RecordingReceiver {
+    stop: CancellationToken,
+    tracks: Arc<Mutex<Option<Tracks>>>,
+}
+
+#[serenity::async_trait]
+impl EventHandler for RecordingReceiver {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if self.stop.is_cancelled() {
+            return Some(Event::Cancel);
+        }
+
+        let mut tracks = self.tracks.lock().unwrap();
+        let Some(tracks) = tracks.as_mut() else {
+            return Some(Event::Cancel);
+        };
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => {
+                if let Some(user_id) = speaking.user_id {
+                    tracks.users.insert(speaking.ssrc, UserId::new(user_id.0));
+                }
+            }
+            EventContext::VoiceTick(tick) => {
+                if let Err(e) = tracks.tick(tick) {
+                    warn!("Failed to record voice: {}", e);
+                    self.stop.cancel();
+                } else if tracks.len() >= MAX_RECORDING_BYTES {
+                    tracks.truncated = true;
+                    self.stop.cancel();
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
@@ -0,0 +1,230 @@
+//! Just enough of Ogg Opus (RFC 7845) to write mono voice recordings.
+
+use audiopus::coder::Encoder;
+use audiopus::{Application, Bitrate, Channels, SampleRate};
+
+/// Samples per Opus frame, 20 ms at 48 kHz like songbird's ticks.
+pub const FRAME_SAMPLES: usize = 960;
+const BITRATE: i32 = 24000;
+/// Samples the encoder delays its output by, players skip them.
+const PRE_SKIP: u16 = 312;
+const MAX_PACKET_SIZE: usize = 1275;
+/// Packets per Ogg page, one second of audio.
+const PACKETS_PER_PAGE: usize = 50;
+const MAX_SEGMENTS: usize = 255;
+
+const BEGINNING_OF_STREAM: u8 = 0x02;
+const END_OF_STREAM: u8 = 0x04;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]
+    })
+}
+
+/// Encodes 48 kHz mono audio into an Ogg Opus file in memory.
+pub struct OpusWriter {
+    encoder: Encoder,
+    serial: u32,
+    sequence: u32,
+    ogg: Vec<u8>,
+    /// Packets of the page being filled.
+    packets: Vec<Vec<u8>>,
+    segments: usize,
+    /// Samples encoded so far, including the encoder delay.
+    samples: u64,
+}
+
+impl OpusWriter {
+    /// Starts a stream, `serial` only has to differ between streams of the same file.
+    pub fn new(serial: u32) -> Result<Self, String> {
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Voip)
+            .map_err(|e| format!("Failed to create Opus encoder: {}", e))?;
+        encoder
+            .set_bitrate(Bitrate::BitsPerSecond(BITRATE))
+            .map_err(|e| format!("Failed to set Opus bitrate: {}", e))?;
+
+        let mut writer = Self {
+            encoder,
+            serial,
+            sequence: 0,
+            ogg: Vec::new(),
+            packets: Vec::new(),
+            segments: 0,
+            samples: 0,
+        };
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1); // version
+        head.push(1); // mono
+        head.extend_from_slice(&PRE_SKIP.to_le_bytes());
+        head.extend_from_slice(&48000u32.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+        head.push(0); // channel mapping family
+        writer.write_page(&[head], 0, BEGINNING_OF_STREAM);
+
+        let vendor = env!("CARGO_PKG_NAME").as_bytes();
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor);
+        tags.extend_from_slice(&0u32.to_le_bytes()); // no comments
+        writer.write_page(&[tags], 0, 0);
+
+        Ok(writer)
+    }
+
+    /// Encodes one frame of `FRAME_SAMPLES` samples without adding it to the stream.
+    pub fn encode(&mut self, frame: &[i16]) -> Result<Vec<u8>, String> {
+        let mut packet = [0u8; MAX_PACKET_SIZE];
+        let len = self
+            .encoder
+            .encode(frame, &mut packet)
+            .map_err(|e| format!("Failed to encode audio: {}", e))?;
+        Ok(packet[..len].to_vec())
+    }
+
+    /// Encodes one frame of `FRAME_SAMPLES` samples.
+    pub fn push(&mut self, frame: &[i16]) -> Result<(), String> {
+        let packet = self.encode(frame)?;
+        self.push_packet(packet);
+        Ok(())
+    }
+
+    /// Adds an already encoded frame, e.g. silence encoded once and reused.
+    pub fn push_packet(&mut self, packet: Vec<u8>) {
+        let segments = packet.len() / 255 + 1;
+        if self.segments + segments > MAX_SEGMENTS {
+            self.flush(0);
+        }
+
+        self.samples += FRAME_SAMPLES as u64;
+        self.segments += segments;
+        self.packets.push(packet);
+        if self.packets.len() >= PACKETS_PER_PAGE {
+            self.flush(0);
+        }
+    }
+
+    /// Size of the file so far.
+    pub fn size(&self) -> usize {
+        self.ogg.len() + self.packets.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.flush(END_OF_STREAM);
+        self.ogg
+    }
+
+    fn flush(&mut self, header_type: u8) {
+        let packets = std::mem::take(&mut self.packets);
+        self.segments = 0;
+        self.write_page(&packets, self.samples, header_type);
+    }
+
+    fn write_page(&mut self, packets: &[Vec<u8>], granule: u64, header_type: u8) {
+        let start = self.ogg.len();
+
+        self.ogg.extend_from_slice(b"OggS");
+        self.ogg.push(0); // version
+        self.ogg.push(header_type);
+        self.ogg.extend_from_slice(&granule.to_le_bytes());
+        self.ogg.extend_from_slice(&self.serial.to_le_bytes());
+        self.ogg.extend_from_slice(&self.sequence.to_le_bytes());
+        let crc_offset = self.ogg.len();
+        self.ogg.extend_from_slice(&0u32.to_le_bytes());
+
+        // Packets are split into segments of 255 bytes, a shorter one ends the packet
+        let lacing: Vec<u8> = packets
+            .iter()
+            .flat_map(|packet| {
+                std::iter::repeat_n(255, packet.len() / 255).chain([(packet.len() % 255) as u8])
+            })
+            .collect();
+        self.ogg.push(lacing.len() as u8);
+        self.ogg.extend_from_slice(&lacing);
+        for packet in packets {
+            self.ogg.extend_from_slice(packet);
+        }
+
+        let checksum = crc(&self.ogg[start..]);
+        self.ogg[crc_offset..crc_offset + 4].copy_from_slice(&checksum.to_le_bytes());
+        self.sequence += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header type and lacing values of every page, checking each page's CRC on the way.
+    fn pages(ogg: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut pages = Vec::new();
+        let mut rest = ogg;
+        while !rest.is_empty() {
+            assert_eq!(&rest[..4], b"OggS");
+            let segments = rest[26] as usize;
+            let lacing = rest[27..27 + segments].to_vec();
+            let len = 27 + segments + lacing.iter().map(|&len| len as usize).sum::<usize>();
+
+            let mut page = rest[..len].to_vec();
+            let checksum = u32::from_le_bytes(page[22..26].try_into().unwrap());
+            page[22..26].fill(0);
+            assert_eq!(crc(&page), checksum);
+
+            pages.push((rest[5], lacing));
+            rest = &rest[len..];
+        }
+        pages
+    }
+
+    #[test]
+    fn crc_matches_the_ogg_polynomial() {
+        assert_eq!(crc(b""), 0);
+        assert_eq!(crc(b"123456789"), 0x89a1_897f);
+    }
+
+    #[test]
+    fn ends_packets_of_whole_segments_with_an_empty_one() {
+        let mut writer = OpusWriter::new(0).unwrap();
+        writer.push_packet(vec![1; 510]);
+        writer.push_packet(vec![2; 3]);
+
+        let pages = pages(&writer.finish());
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[2], (END_OF_STREAM, vec![255, 255, 0, 3]));
+    }
+
+    #[test]
+    fn starts_a_new_page_before_running_out_of_segments() {
+        let mut writer = OpusWriter::new(0).unwrap();
+        // Six segments each, only 42 of them fit on a page
+        for _ in 0..50 {
+            writer.push_packet(vec![0; MAX_PACKET_SIZE]);
+        }
+
+        let pages = pages(&writer.finish());
+        let lacing: Vec<usize> = pages[2..].iter().map(|(_, lacing)| lacing.len()).collect();
+        assert_eq!(lacing, [42 * 6, 8 * 6]);
+        assert!(pages.iter().all(|(_, lacing)| lacing.len() <= MAX_SEGMENTS));
+    }
+}
@@ -0,0 +1,145 @@
+use crate::triggers::TriggerRule;
+use poise::serenity_prelude as serenity;
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, RoleId, UserId};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::sync::RwLock;
+use tracing::warn;
+
+const SETTINGS_PATH: &str = "data/settings.json";
+
+/// What happens to the music while speech is played.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, poise::ChoiceParameter,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeechMusic {
+    /// Pause the music and resume it afterwards
+    #[default]
+    Pause,
+    /// Lower the music's volume and fade it back up afterwards
+    Duck,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Default TTS voice for everyone in the guild.
+    pub voice: Option<String>,
+    /// Read emoji out by name instead of skipping them.
+    pub read_emoji: bool,
+    /// Messages posted here are read aloud to the bot's voice channel.
+    pub tts_channel: Option<ChannelId>,
+    pub speech_music: SpeechMusic,
+    /// Music volume while ducked, in percent.
+    pub duck_volume: Option<u8>,
+    /// Time the ducked music takes to fade down and back up.
+    pub duck_fade_ms: Option<u64>,
+    /// Rules answering matching messages, in the order they were added.
+    pub triggers: Vec<TriggerRule>,
+    /// Colour of the bot's replies, see `commands::ui::Theme`.
+    pub accent_colour: Option<u32>,
+    pub warning_colour: Option<u32>,
+    /// Members with this role may control the music from outside the bot's voice channel.
+    pub dj_role: Option<RoleId>,
+    /// Post a "Now playing" card whenever a queued track starts.
+    pub announce_tracks: bool,
+    /// Queue related tracks when the queue runs out.
+    pub autoplay: bool,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserSettings {
+    /// TTS voice of the user, takes precedence over the guild's voice.
+    pub voice: Option<String>,
+    /// Don't read the user's messages in the TTS channel aloud.
+    pub tts_opt_out: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    guilds: HashMap<u64, GuildSettings>,
+    users: HashMap<u64, UserSettings>,
+}
+
+/// Per-guild and per-user settings, persisted as JSON under `data/`.
+pub struct SettingsStore {
+    path: PathBuf,
+    settings: RwLock<Settings>,
+}
+
+impl SettingsStore {
+    /// Loads the settings file, starting out empty if it doesn't exist or can't be read.
+    pub async fn load() -> Self {
+        let path = PathBuf::from(SETTINGS_PATH);
+
+        let settings = match fs::read_to_string(&path).await {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid settings file {:?}: {}", path, e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        };
+
+        Self {
+            path,
+            settings: RwLock::new(settings),
+        }
+    }
+
+    pub async fn guild(&self, guild_id: GuildId) -> GuildSettings {
+        let settings = self.settings.read().await;
+        settings
+            .guilds
+            .get(&guild_id.get())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn user(&self, user_id: UserId) -> UserSettings {
+        let settings = self.settings.read().await;
+        settings
+            .users
+            .get(&user_id.get())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn update_guild(
+        &self,
+        guild_id: GuildId,
+        update: impl FnOnce(&mut GuildSettings),
+    ) -> Result<(), String> {
+        let mut settings = self.settings.write().await;
+        update(settings.guilds.entry(guild_id.get()).or_default());
+        self.save(&settings).await
+    }
+
+    pub async fn update_user(
+        &self,
+        user_id: UserId,
+        update: impl FnOnce(&mut UserSettings),
+    ) -> Result<(), String> {
+        let mut settings = self.settings.write().await;
+        update(settings.users.entry(user_id.get()).or_default());
+        self.save(&settings).await
+    }
+
+    async fn save(&self, settings: &Settings) -> Result<(), String> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)
+                .await
+                .map_err(|e| format!("Could not create `{}`: {}", dir.display(), e))?;
+        }
+
+        let content = serde_json::to_string_pretty(settings)
+            .map_err(|e| format!("Could not serialize settings: {}", e))?;
+        fs::write(&self.path, content)
+            .await
+            .map_err(|e| format!("Could not write `{}`: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,137 @@
+//! Short sound clips uploaded per guild, stored under `data/sounds/<guild id>/<name>.<ext>`.
+
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tokio::fs;
+use tokio::task;
+
+const SOUNDS_DIR: &str = "data/sounds";
+pub const EXTENSIONS: &[&str] = &["mp3", "wav", "ogg", "flac"];
+pub const MAX_SOUND_SIZE: u64 = 2 * 1024 * 1024;
+pub const MAX_SOUND_DURATION: Duration = Duration::from_secs(15);
+pub const MAX_SOUNDS_PER_GUILD: usize = 50;
+const MAX_NAME_LENGTH: usize = 32;
+
+fn guild_dir(guild_id: GuildId) -> PathBuf {
+    Path::new(SOUNDS_DIR).join(guild_id.to_string())
+}
+
+/// Sound names are used as file names, so only lowercase letters, digits, `-` and `_` are allowed.
+pub fn validate_name(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!(
+            "Names must be between 1 and {} characters long.",
+            MAX_NAME_LENGTH
+        ));
+    }
+    if !name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_'))
+    {
+        return Err("Names may only contain letters, digits, `-` and `_`.".to_string());
+    }
+    Ok(name)
+}
+
+/// Names of all sounds of the guild, sorted alphabetically.
+pub async fn list(guild_id: GuildId) -> Vec<String> {
+    let mut names = Vec::new();
+    let Ok(mut entries) = fs::read_dir(guild_dir(guild_id)).await else {
+        return names;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let is_sound = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| EXTENSIONS.contains(&extension));
+        if let (true, Some(name)) = (is_sound, path.file_stem().and_then(|stem| stem.to_str())) {
+            names.push(name.to_string());
+        }
+    }
+
+    names.sort();
+    names
+}
+
+/// Path of the sound called `name`, if it exists.
+pub async fn find(guild_id: GuildId, name: &str) -> Option<PathBuf> {
+    // Names come straight from users, never let them reach outside of the guild's directory
+    let name = validate_name(name).ok()?;
+    let dir = guild_dir(guild_id);
+    for extension in EXTENSIONS {
+        let path = dir.join(format!("{}.{}", name, extension));
+        if fs::try_exists(&path).await.unwrap_or(false) {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Reads the length of an audio file, failing if symphonia can't make sense of it.
+pub async fn probe_duration(audio: Vec<u8>, extension: &str) -> Result<Duration, String> {
+    let extension = extension.to_string();
+    task::spawn_blocking(move || {
+        let source = MediaSourceStream::new(Box::new(Cursor::new(audio)), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension(&extension);
+
+        let probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                source,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(|e| format!("Unsupported audio: {}", e))?;
+        let mut format = probed.format;
+
+        let track = format
+            .default_track()
+            .ok_or("The file contains no audio.".to_string())?;
+        let track_id = track.id;
+        let params = track.codec_params.clone();
+
+        if let (Some(frames), Some(sample_rate)) = (params.n_frames, params.sample_rate) {
+            return Ok(Duration::from_secs_f64(frames as f64 / sample_rate as f64));
+        }
+
+        // No length in the header, add up the packets instead
+        let time_base = params
+            .time_base
+            .ok_or("Could not determine the length of the audio.".to_string())?;
+        let mut total = 0;
+        while let Ok(packet) = format.next_packet() {
+            if packet.track_id() == track_id {
+                total += packet.dur;
+            }
+        }
+        let time = time_base.calc_time(total);
+        Ok(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+    })
+    .await
+    .map_err(|e| format!("Failed to read audio: {}", e))?
+}
+
+pub async fn save(guild_id: GuildId, name: &str, extension: &str, audio: &[u8]) -> io::Result<()> {
+    let dir = guild_dir(guild_id);
+    fs::create_dir_all(&dir).await?;
+    fs::write(dir.join(format!("{}.{}", name, extension)), audio).await
+}
+
+/// Deletes the sound called `name`, returns false if it doesn't exist.
+pub async fn remove(guild_id: GuildId, name: &str) -> io::Result<bool> {
+    match find(guild_id, name).await {
+        Some(path) => fs::remove_file(path).await.map(|_| true),
+        None => Ok(false),
+    }
+}
@@ -0,0 +1,228 @@
+//! Conversations in voice: what the user says is transcribed, answered by the LLM and spoken back.
+//! Talking while the bot answers interrupts it.
+
+use super::listener::Heard;
+use super::whisper;
+use crate::llm::{self, ReplyTarget};
+use crate::player::GuildPlayer;
+use crate::tts::normalize::{Language, NormalizeOptions, cache_resolver, normalize};
+use crate::tts::queue::SpeechId;
+use crate::tts::{self, voices};
+use crate::{Data, Error};
+use poise::serenity_prelude as serenity;
+use serenity::Context;
+use serenity::all::{ChannelId, CreateMessage, GetMessages, GuildId, UserId};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+
+/// Messages of the text channel given to the model as context, these include earlier turns.
+const HISTORY_LENGTH: u8 = 10;
+
+/// A turn of the conversation that is being answered or spoken.
+struct Turn {
+    state: Arc<TurnState>,
+    task: JoinHandle<()>,
+}
+
+/// What the answering task shares with the conversation.
+struct TurnState {
+    cancel: CancellationToken,
+    /// The answer once it was queued for speaking.
+    speech: Mutex<Option<SpeechId>>,
+}
+
+impl Turn {
+    /// Stops the turn wherever it is, gives up its place in the LLM queue and takes back its answer.
+    /// Speech and LLM requests of others are left alone.
+    async fn interrupt(self, data: &Data, guild_id: GuildId) {
+        self.state.cancel.cancel();
+        let _ = self.task.await;
+
+        let speech = self.state.speech.lock().unwrap().take();
+        if let Some(speech) = speech {
+            data.speech_queue.cancel(guild_id, speech).await;
+        }
+    }
+}
+
+/// Answers everything `user_id` says until the session is cancelled.
+pub(super) async fn run(
+    ctx: Context,
+    data: Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    user_id: UserId,
+    session: CancellationToken,
+    mut heard: mpsc::UnboundedReceiver<Heard>,
+) {
+    let mut turn: Option<Turn> = None;
+
+    loop {
+        let next = tokio::select! {
+            _ = session.cancelled() => break,
+            next = heard.recv() => next,
+        };
+
+        match next {
+            // Barge-in, the user talks over the answer
+            Some(Heard::Speaking(speaker)) if speaker == user_id => {
+                if let Some(turn) = turn.take() {
+                    turn.interrupt(&data, guild_id).await;
+                }
+            }
+            Some(Heard::Utterance {
+                user_id: speaker,
+                samples,
+            }) if speaker == user_id => {
+                if let Some(turn) = turn.take() {
+                    turn.interrupt(&data, guild_id).await;
+                }
+
+                let state = Arc::new(TurnState {
+                    cancel: session.child_token(),
+                    speech: Mutex::new(None),
+                });
+                turn = Some(Turn {
+                    task: tokio::spawn(answer(
+                        ctx.clone(),
+                        data.clone(),
+                        guild_id,
+                        channel_id,
+                        user_id,
+                        samples,
+                        state.clone(),
+                    )),
+                    state,
+                });
+            }
+            // Everyone else in the channel is ignored
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    if let Some(turn) = turn.take() {
+        turn.interrupt(&data, guild_id).await;
+    }
+    data.listener.finished(guild_id, &session);
+}
+
+async fn answer(
+    ctx: Context,
+    data: Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    user_id: UserId,
+    samples: Vec<f32>,
+    state: Arc<TurnState>,
+) {
+    if let Err(e) = answer_turn(&ctx, &data, guild_id, channel_id, user_id, samples, &state).await {
+        println!("Failed to answer in conversation: {}", e);
+    }
+}
+
+async fn answer_turn(
+    ctx: &Context,
+    data: &Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    user_id: UserId,
+    samples: Vec<f32>,
+    state: &TurnState,
+) -> Result<(), Error> {
+    let cancel = &state.cancel;
+    let voice = voices::resolve(&data.settings, Some(guild_id), user_id).await;
+    let transcript = match whisper::transcribe(samples, Language::of_voice(&voice)).await {
+        Ok(transcript) => transcript,
+        Err(e) => {
+            warn!("Failed to transcribe voice: {}", e);
+            return Ok(());
+        }
+    };
+    if transcript.is_empty() || cancel.is_cancelled() {
+        return Ok(());
+    }
+
+    let name = ctx
+        .cache
+        .user(user_id)
+        .map(|user| user.display_name().to_string())
+        .unwrap_or_else(|| user_id.to_string());
+    let said = channel_id
+        .send_message(
+            &ctx.http,
+            CreateMessage::new()
+                .content(format!(":studio_microphone: **{}**: {}", name, transcript)),
+        )
+        .await?;
+
+    let history = channel_id
+        .messages(
+            &ctx.http,
+            GetMessages::default().before(&said).limit(HISTORY_LENGTH),
+        )
+        .await?;
+    let request = llm::build_request(
+        &llm::DEFAULT_PERSONA,
+        history.into_iter().rev().collect(),
+        user_id,
+        &transcript,
+    );
+
+    // The answer is streamed into the text channel, that is the bot's side of the transcript
+    let mut reply = channel_id
+        .send_message(&ctx.http, CreateMessage::new().content("-"))
+        .await?;
+    // Dropping the request on barge-in also frees its place in the LLM queue
+    let response = tokio::select! {
+        response = llm::chat(
+            ctx,
+            data,
+            &mut ReplyTarget::Message(&ctx.http, &mut reply),
+            Some(guild_id),
+            user_id,
+            request,
+        ) => response?,
+        _ = cancel.cancelled() => return Ok(()),
+    };
+    if response.trim().is_empty() || cancel.is_cancelled() {
+        return Ok(());
+    }
+
+    // The user may have left in the meantime
+    let Some(handler_lock) = GuildPlayer::shared_with(ctx, guild_id, user_id)
+        .await
+        .map(|player| player.call())
+    else {
+        return Ok(());
+    };
+
+    let options = NormalizeOptions::for_voice(&data.settings, Some(guild_id), &voice).await;
+    let text = normalize(&response, &options, cache_resolver(ctx, Some(guild_id)));
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let tts::Speech { input, audio } = match tts::synthesize(&text, &voice).await {
+        Ok(speech) => speech,
+        Err(e) => {
+            println!("Failed to generate TTS: {}", e);
+            return Err(Error::Tts("Failed to generate TTS"));
+        }
+    };
+    drop(audio);
+
+    if cancel.is_cancelled() {
+        return Ok(());
+    }
+    let id = data
+        .speech_queue
+        .play(guild_id, handler_lock, input)
+        .await
+        .map_err(Error::Voice)?;
+    *state.speech.lock().unwrap() = Some(id);
+    Ok(())
+}
@@ -0,0 +1,433 @@
+//! Opt-in listening mode: voice is received per speaker, cut into utterances and transcribed.
+//! Utterances starting with the wake word are run as music commands, or in a conversation
+//! everything the conversing user says is answered.
+
+use super::converse;
+use super::whisper::{self, SAMPLE_RATE};
+use crate::Data;
+use crate::commands::ui::Theme;
+use crate::llm::tools;
+use crate::receive;
+use crate::tts::normalize::Language;
+use crate::tts::voices;
+use poise::serenity_prelude as serenity;
+use serenity::Context;
+use serenity::all::{ChannelId, CreateMessage, GuildId, UserId};
+use songbird::{Call, CoreEvent, Event, EventContext, EventHandler};
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+const DEFAULT_WAKE_WORD: &str = "computer";
+/// Silence after which an utterance is over, in ticks of 20 ms.
+const END_SILENCE_TICKS: u32 = 40;
+const MIN_UTTERANCE_SAMPLES: usize = SAMPLE_RATE / 2;
+const MAX_UTTERANCE_SAMPLES: usize = 20 * SAMPLE_RATE;
+/// Received voice is decoded at 48 kHz, Whisper wants 16 kHz.
+const RATE_FACTOR: usize = receive::SAMPLE_RATE / SAMPLE_RATE;
+/// Voice after which a speaker counts as talking, shorter noises don't interrupt the bot.
+const BARGE_IN_SAMPLES: usize = SAMPLE_RATE * 3 / 10;
+
+/// The word utterances have to start with to be run as a command.
+pub fn wake_word() -> String {
+    env::var("STT_WAKE_WORD")
+        .unwrap_or_else(|_| DEFAULT_WAKE_WORD.to_string())
+        .to_lowercase()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VoiceCommand {
+    Play(String),
+    Skip,
+    Pause,
+    Resume,
+}
+
+impl VoiceCommand {
+    /// The music tool running the command.
+    fn tool(&self) -> &'static str {
+        match self {
+            VoiceCommand::Play(_) => "enqueue",
+            VoiceCommand::Skip => "skip",
+            VoiceCommand::Pause => "pause",
+            VoiceCommand::Resume => "resume",
+        }
+    }
+}
+
+/// Parses a transcript like "Computer, play some lofi." into a command.
+/// Returns `None` if it doesn't start with the wake word or isn't a known command.
+pub fn parse_command(transcript: &str, wake_word: &str) -> Option<VoiceCommand> {
+    let cleaned: String = transcript
+        .to_lowercase()
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { ' ' })
+        .collect();
+    let words: Vec<&str> = cleaned.split_whitespace().collect();
+    let wake_words: Vec<&str> = wake_word.split_whitespace().collect();
+    if wake_words.is_empty() {
+        return None;
+    }
+
+    // Allow a greeting like "hey" or "ok" in front of the wake word
+    let start = (0..=2)
+        .filter(|start| words.len() >= start + wake_words.len())
+        .find(|start| words[*start..start + wake_words.len()] == wake_words[..])?;
+    let rest = &words[start + wake_words.len()..];
+    let (verb, arguments) = rest.split_first()?;
+
+    match *verb {
+        "play" | "spiel" | "spiele" => {
+            let query = arguments.join(" ");
+            (!query.is_empty()).then_some(VoiceCommand::Play(query))
+        }
+        "skip" | "next" | "überspringen" | "überspringe" | "nächstes" | "nächster" => {
+            Some(VoiceCommand::Skip)
+        }
+        "pause" | "stop" | "stopp" | "halt" => Some(VoiceCommand::Pause),
+        "resume" | "continue" | "weiter" | "fortsetzen" => Some(VoiceCommand::Resume),
+        _ => None,
+    }
+}
+
+/// What the bot is listening for.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ListenMode {
+    /// Music commands of everyone, starting with the wake word.
+    Commands,
+    /// Anything the user says is answered by the LLM.
+    Converse(UserId),
+}
+
+/// What was heard of one speaker.
+pub(super) enum Heard {
+    /// The speaker has been talking for a moment.
+    Speaking(UserId),
+    /// Audio of the speaker that ended with a pause.
+    Utterance { user_id: UserId, samples: Vec<f32> },
+}
+
+/// Guilds the bot is listening in.
+#[derive(Default)]
+pub struct Listener {
+    sessions: Mutex<HashMap<GuildId, CancellationToken>>,
+}
+
+impl Listener {
+    pub fn is_listening(&self, guild_id: GuildId) -> bool {
+        self.sessions.lock().unwrap().contains_key(&guild_id)
+    }
+
+    /// Starts receiving voice of the call, results are posted to `channel_id`.
+    pub async fn start(
+        &self,
+        ctx: &Context,
+        data: &Data,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        handler_lock: Arc<tokio::sync::Mutex<Call>>,
+        mode: ListenMode,
+    ) -> Result<(), &'static str> {
+        // Cancelled by `/listen stop` or `/converse stop`
+        let session = CancellationToken::new();
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            if sessions.contains_key(&guild_id) {
+                return Err("Already listening.");
+            }
+            sessions.insert(guild_id, session.clone());
+        }
+
+        let (heard, receiver) = mpsc::unbounded_channel();
+        let handler = VoiceReceiver {
+            session: session.clone(),
+            speakers: Arc::new(Mutex::new(Speakers::default())),
+            heard,
+        };
+
+        {
+            let mut call = handler_lock.lock().await;
+            for event in [
+                CoreEvent::SpeakingStateUpdate,
+                CoreEvent::VoiceTick,
+                CoreEvent::ClientDisconnect,
+            ] {
+                call.add_global_event(event.into(), handler.clone());
+            }
+            if let Err(e) = receive::update(data, guild_id, &mut call).await {
+                self.sessions.lock().unwrap().remove(&guild_id);
+                session.cancel();
+                return Err(e);
+            }
+        }
+
+        let (ctx, data) = (ctx.clone(), data.clone());
+        match mode {
+            ListenMode::Commands => {
+                tokio::spawn(run_commands(
+                    ctx, data, guild_id, channel_id, session, receiver,
+                ));
+            }
+            ListenMode::Converse(user_id) => {
+                tokio::spawn(converse::run(
+                    ctx, data, guild_id, channel_id, user_id, session, receiver,
+                ));
+            }
+        }
+        info!("Listening in {}", guild_id);
+        Ok(())
+    }
+
+    /// Stops listening, returns false if the bot wasn't listening.
+    pub async fn stop(
+        &self,
+        data: &Data,
+        guild_id: GuildId,
+        handler_lock: Option<Arc<tokio::sync::Mutex<Call>>>,
+    ) -> bool {
+        let Some(session) = self.sessions.lock().unwrap().remove(&guild_id) else {
+            return false;
+        };
+        // The receivers remove themselves on their next event
+        session.cancel();
+
+        if let Some(handler_lock) = handler_lock {
+            let _ = receive::update(data, guild_id, &mut *handler_lock.lock().await).await;
+        }
+        info!("Stopped listening in {}", guild_id);
+        true
+    }
+
+    /// Forgets `session` once its receivers are gone, e.g. because the bot left the channel.
+    pub(super) fn finished(&self, guild_id: GuildId, session: &CancellationToken) {
+        // A cancelled session was already removed, and a guild only gets a new one after that
+        if !session.is_cancelled() {
+            self.sessions.lock().unwrap().remove(&guild_id);
+        }
+    }
+}
+
+/// Transcribes utterances one after another and runs the commands in them.
+async fn run_commands(
+    ctx: Context,
+    data: Data,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    session: CancellationToken,
+    mut heard: mpsc::UnboundedReceiver<Heard>,
+) {
+    let wake_word = wake_word();
+
+    loop {
+        let utterance = tokio::select! {
+            _ = session.cancelled() => break,
+            utterance = heard.recv() => utterance,
+        };
+        let (user_id, samples) = match utterance {
+            Some(Heard::Utterance { user_id, samples }) => (user_id, samples),
+            Some(Heard::Speaking(_)) => continue,
+            None => break,
+        };
+        if ctx.cache.user(user_id).is_some_and(|user| user.bot) {
+            continue;
+        }
+
+        let voice = voices::resolve(&data.settings, Some(guild_id), user_id).await;
+        let transcript = match whisper::transcribe(samples, Language::of_voice(&voice)).await {
+            Ok(transcript) => transcript,
+            Err(e) => {
+                warn!("Failed to transcribe voice: {}", e);
+                continue;
+            }
+        };
+
+        let Some(command) = parse_command(&transcript, &wake_word) else {
+            continue;
+        };
+        info!("Voice command from {}: {:?}", user_id, command);
+
+        let query = match &command {
+            VoiceCommand::Play(query) => Some(query.clone()),
+            _ => None,
+        };
+        let result = tools::run(
+            &ctx,
+            &data,
+            Some(guild_id),
+            channel_id,
+            user_id,
+            command.tool(),
+            query,
+        )
+        .await;
+
+        let message = CreateMessage::new().embed(
+            Theme::of(&data.settings, Some(guild_id))
+                .await
+                .success(format!(":studio_microphone: \"{}\"", transcript))
+                .description(format!("<@{}>: {}", user_id, result)),
+        );
+        if let Err(e) = channel_id.send_message(&ctx.http, message).await {
+            println!("Failed to post voice command: {}", e);
+        }
+    }
+
+    data.listener.finished(guild_id, &session);
+}
+
+/// Audio received from one SSRC since it started speaking.
+#[derive(Default)]
+struct Speaker {
+    samples: Vec<f32>,
+    silent_ticks: u32,
+    /// Whether `Heard::Speaking` was sent for this utterance.
+    announced: bool,
+}
+
+#[derive(Default)]
+struct Speakers {
+    users: HashMap<u32, UserId>,
+    speaking: HashMap<u32, Speaker>,
+}
+
+/// Collects decoded voice per speaker and hands out finished utterances.
+#[derive(Clone)]
+struct VoiceReceiver {
+    session: CancellationToken,
+    speakers: Arc<Mutex<Speakers>>,
+    heard: mpsc::UnboundedSender<Heard>,
+}
+
+impl VoiceReceiver {
+    fn finish(&self, speakers: &mut Speakers, ssrc: u32) {
+        let Some(speaker) = speakers.speaking.remove(&ssrc) else {
+            return;
+        };
+        // Too short for a command, most likely a cough or a click
+        if speaker.samples.len() < MIN_UTTERANCE_SAMPLES {
+            return;
+        }
+        if let Some(user_id) = speakers.users.get(&ssrc) {
+            let _ = self.heard.send(Heard::Utterance {
+                user_id: *user_id,
+                samples: speaker.samples,
+            });
+        }
+    }
+}
+
+#[serenity::async_trait]
+impl EventHandler for VoiceReceiver {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if self.session.is_cancelled() {
+            return Some(Event::Cancel);
+        }
+
+        let mut speakers = self.speakers.lock().unwrap();
+        match ctx {
+            EventContext::SpeakingStateUpdate(speaking) => {
+                if let Some(user_id) = speaking.user_id {
+                    speakers.users.insert(speaking.ssrc, UserId::new(user_id.0));
+                }
+            }
+            EventContext::ClientDisconnect(disconnect) => {
+                speakers
+                    .users
+                    .retain(|_, user_id| user_id.get() != disconnect.user_id.0);
+            }
+            EventContext::VoiceTick(tick) => {
+                for (ssrc, voice) in &tick.speaking {
+                    let Some(decoded) = &voice.decoded_voice else {
+                        continue;
+                    };
+                    let user_id = speakers.users.get(ssrc).copied();
+                    let speaker = speakers.speaking.entry(*ssrc).or_default();
+                    speaker.silent_ticks = 0;
+                    // Averaging three samples filters out just enough for 16 kHz speech
+                    speaker
+                        .samples
+                        .extend(decoded.chunks(RATE_FACTOR).map(|chunk| {
+                            chunk.iter().map(|sample| *sample as f32).sum::<f32>()
+                                / (chunk.len() as f32 * 32768.0)
+                        }));
+
+                    let talking = !speaker.announced && speaker.samples.len() >= BARGE_IN_SAMPLES;
+                    if let Some(user_id) = user_id.filter(|_| talking) {
+                        speaker.announced = true;
+                        let _ = self.heard.send(Heard::Speaking(user_id));
+                    }
+                }
+
+                let finished: Vec<u32> = speakers
+                    .speaking
+                    .iter_mut()
+                    .filter_map(|(ssrc, speaker)| {
+                        if !tick.speaking.contains_key(ssrc) {
+                            speaker.silent_ticks += 1;
+                        }
+                        let done = speaker.silent_ticks >= END_SILENCE_TICKS
+                            || speaker.samples.len() >= MAX_UTTERANCE_SAMPLES;
+                        done.then_some(*ssrc)
+                    })
+                    .collect();
+                for ssrc in finished {
+                    self.finish(&mut speakers, ssrc);
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_command_after_a_greeting() {
+        assert_eq!(
+            parse_command("Hey computer, play some lofi.", "computer"),
+            Some(VoiceCommand::Play("some lofi".to_string()))
+        );
+        assert_eq!(
+            parse_command("Okay, hey Mister Bot skip!", "mister bot"),
+            Some(VoiceCommand::Skip)
+        );
+    }
+
+    #[test]
+    fn ignores_utterances_without_the_wake_word() {
+        assert_eq!(parse_command("Play some lofi.", "computer"), None);
+        assert_eq!(
+            parse_command("I told the computer to pause", "computer"),
+            None
+        );
+        assert_eq!(parse_command("Computer", "computer"), None);
+    }
+
+    #[test]
+    fn play_needs_a_query() {
+        assert_eq!(parse_command("Computer, play.", "computer"), None);
+    }
+
+    #[test]
+    fn understands_german_verbs() {
+        assert_eq!(
+            parse_command("Computer, spiele Die Ärzte!", "computer"),
+            Some(VoiceCommand::Play("die ärzte".to_string()))
+        );
+        assert_eq!(
+            parse_command("Computer nächstes Lied", "computer"),
+            Some(VoiceCommand::Skip)
+        );
+        assert_eq!(
+            parse_command("Computer, weiter.", "computer"),
+            Some(VoiceCommand::Resume)
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod converse;
+pub mod listener;
+pub mod whisper;
@@ -0,0 +1,356 @@
+//! Speech recognition with a Whisper model exported to ONNX, e.g. through
+//! `optimum-cli export onnx --model openai/whisper-base models/whisper`.
+
+use crate::tts::normalize::Language;
+use ort::session::Session;
+use ort::value::Tensor;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::f64::consts::PI;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use tokio::sync::Mutex;
+use tokio::task;
+use tracing::info;
+
+const DEFAULT_MODEL_DIR: &str = "models/whisper";
+pub const SAMPLE_RATE: usize = 16000;
+const N_FFT: usize = 400;
+const HOP_LENGTH: usize = 160;
+/// Whisper always looks at 30 seconds, shorter audio is padded with silence.
+const CHUNK_SAMPLES: usize = 30 * SAMPLE_RATE;
+const N_FRAMES: usize = CHUNK_SAMPLES / HOP_LENGTH;
+const DEFAULT_MEL_BINS: usize = 80;
+/// Voice commands are short, anything longer is cut off.
+const MAX_TOKENS: usize = 48;
+
+pub struct Whisper {
+    encoder: Session,
+    decoder: Session,
+    mel_bins: usize,
+    filters: Vec<f32>,
+    tokenizer: Tokenizer,
+}
+
+static MODEL: LazyLock<Mutex<Option<Arc<Whisper>>>> = LazyLock::new(|| Mutex::new(None));
+
+fn model_dir() -> PathBuf {
+    PathBuf::from(env::var("STT_MODEL_DIR").unwrap_or_else(|_| DEFAULT_MODEL_DIR.to_string()))
+}
+
+/// Returns the Whisper model, loading it on first use.
+pub async fn get() -> Result<Arc<Whisper>, String> {
+    let mut model = MODEL.lock().await;
+    if let Some(whisper) = model.as_ref() {
+        return Ok(whisper.clone());
+    }
+
+    let dir = model_dir();
+    let whisper = task::spawn_blocking(move || Whisper::load(&dir))
+        .await
+        .map_err(|e| format!("Failed to load Whisper: {}", e))??;
+
+    info!("Loaded Whisper model from {:?}", model_dir());
+    let whisper = Arc::new(whisper);
+    *model = Some(whisper.clone());
+    Ok(whisper)
+}
+
+/// Transcribes 16 kHz mono `samples`, at most 30 seconds are used.
+pub async fn transcribe(samples: Vec<f32>, language: Language) -> Result<String, String> {
+    let whisper = get().await?;
+    task::spawn_blocking(move || whisper.transcribe(&samples, language))
+        .await
+        .map_err(|e| format!("Failed to transcribe: {}", e))?
+}
+
+impl Whisper {
+    fn load(dir: &Path) -> Result<Self, String> {
+        let session = |file: &str| {
+            let path = dir.join(file);
+            Session::builder()
+                .and_then(|builder| builder.commit_from_file(&path))
+                .map_err(|e| {
+                    format!(
+                        "Failed to load `{}`: {}. Export a Whisper model to `{}` or set `STT_MODEL_DIR`.",
+                        path.display(),
+                        e,
+                        dir.display()
+                    )
+                })
+        };
+
+        // large-v3 switched to 128 bins, the smaller models use 80
+        let mel_bins = std::fs::read_to_string(dir.join("config.json"))
+            .ok()
+            .and_then(|config| serde_json::from_str::<Value>(&config).ok())
+            .and_then(|config| config.get("num_mel_bins").and_then(Value::as_u64))
+            .map_or(DEFAULT_MEL_BINS, |bins| bins as usize);
+
+        Ok(Self {
+            encoder: session("encoder_model.onnx")?,
+            decoder: session("decoder_model.onnx")?,
+            mel_bins,
+            filters: mel_filters(mel_bins),
+            tokenizer: Tokenizer::load(dir)?,
+        })
+    }
+
+    fn transcribe(&self, samples: &[f32], language: Language) -> Result<String, String> {
+        let features = log_mel_spectrogram(samples, &self.filters, self.mel_bins);
+        let features = Tensor::from_array(([1, self.mel_bins, N_FRAMES], features))
+            .map_err(|e| format!("Invalid features: {}", e))?;
+
+        let outputs = self
+            .encoder
+            .run(ort::inputs!["input_features" => features].map_err(|e| e.to_string())?)
+            .map_err(|e| format!("Encoder failed: {}", e))?;
+        let (shape, hidden) = outputs["last_hidden_state"]
+            .try_extract_raw_tensor::<f32>()
+            .map_err(|e| format!("Invalid encoder output: {}", e))?;
+        let shape: Vec<usize> = shape.iter().map(|dim| *dim as usize).collect();
+        let hidden = hidden.to_vec();
+
+        let tokens = &self.tokenizer;
+        let mut input_ids = vec![
+            tokens.start_of_transcript,
+            tokens.language(language)?,
+            tokens.transcribe,
+            tokens.no_timestamps,
+        ];
+        let prompt_len = input_ids.len();
+
+        // Greedy decoding without a key/value cache, commands are only a few tokens long
+        while input_ids.len() - prompt_len < MAX_TOKENS {
+            let ids = Tensor::from_array(([1, input_ids.len()], input_ids.clone()))
+                .map_err(|e| format!("Invalid tokens: {}", e))?;
+            let hidden_states = Tensor::from_array((shape.clone(), hidden.clone()))
+                .map_err(|e| format!("Invalid encoder output: {}", e))?;
+
+            let outputs = self
+                .decoder
+                .run(
+                    ort::inputs![
+                        "input_ids" => ids,
+                        "encoder_hidden_states" => hidden_states,
+                    ]
+                    .map_err(|e| e.to_string())?,
+                )
+                .map_err(|e| format!("Decoder failed: {}", e))?;
+            let (logits_shape, logits) = outputs["logits"]
+                .try_extract_raw_tensor::<f32>()
+                .map_err(|e| format!("Invalid decoder output: {}", e))?;
+
+            let vocab_size = logits_shape[2] as usize;
+            let last = &logits[logits.len() - vocab_size..];
+            let next = last
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(id, _)| id as i64)
+                .ok_or("Decoder returned no logits.")?;
+
+            if next == tokens.end_of_text {
+                break;
+            }
+            input_ids.push(next);
+        }
+
+        Ok(tokens.decode(&input_ids[prompt_len..]).trim().to_string())
+    }
+}
+
+/// Whisper's log mel spectrogram of `samples`, padded or cut to 30 seconds.
+/// Laid out as `[mel_bins, N_FRAMES]`.
+fn log_mel_spectrogram(samples: &[f32], filters: &[f32], mel_bins: usize) -> Vec<f32> {
+    let n_bins = N_FFT / 2 + 1;
+    let mut audio = samples[..samples.len().min(CHUNK_SAMPLES)].to_vec();
+    audio.resize(CHUNK_SAMPLES, 0.0);
+
+    // Centered frames, the audio is mirrored at both ends like `torch.stft` does
+    let pad = N_FFT / 2;
+    let mut padded = Vec::with_capacity(CHUNK_SAMPLES + 2 * pad);
+    padded.extend((1..=pad).rev().map(|i| audio[i]));
+    padded.extend_from_slice(&audio);
+    padded.extend((1..=pad).map(|i| audio[CHUNK_SAMPLES - 1 - i]));
+
+    let window: Vec<f64> = (0..N_FFT)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f64 / N_FFT as f64).cos())
+        .collect();
+    let (cos, sin): (Vec<f64>, Vec<f64>) = (0..n_bins * N_FFT)
+        .map(|i| {
+            let angle = 2.0 * PI * ((i / N_FFT) * (i % N_FFT)) as f64 / N_FFT as f64;
+            (angle.cos(), angle.sin())
+        })
+        .unzip();
+
+    let mut mel = vec![0.0f32; mel_bins * N_FRAMES];
+    let mut power = vec![0.0f64; n_bins];
+    for frame in 0..N_FRAMES {
+        let start = frame * HOP_LENGTH;
+        let input = &padded[start..start + N_FFT];
+        // Most of the 30 seconds are padding, silence stays at zero
+        if input.iter().all(|sample| *sample == 0.0) {
+            continue;
+        }
+
+        for (bin, power) in power.iter_mut().enumerate() {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, sample) in input.iter().enumerate() {
+                let value = *sample as f64 * window[n];
+                re += value * cos[bin * N_FFT + n];
+                im -= value * sin[bin * N_FFT + n];
+            }
+            *power = re * re + im * im;
+        }
+
+        for m in 0..mel_bins {
+            let weights = &filters[m * n_bins..(m + 1) * n_bins];
+            let energy: f64 = weights
+                .iter()
+                .zip(&power)
+                .map(|(weight, power)| *weight as f64 * power)
+                .sum();
+            mel[m * N_FRAMES + frame] = energy as f32;
+        }
+    }
+
+    for value in mel.iter_mut() {
+        *value = value.max(1e-10).log10();
+    }
+    let max = mel.iter().copied().fold(f32::MIN, f32::max);
+    for value in mel.iter_mut() {
+        *value = (value.max(max - 8.0) + 4.0) / 4.0;
+    }
+    mel
+}
+
+fn hz_to_mel(hz: f64) -> f64 {
+    // Slaney's scale: linear up to 1 kHz, logarithmic above
+    let min_log_mel = 15.0;
+    let log_step = 6.4f64.ln() / 27.0;
+    if hz >= 1000.0 {
+        min_log_mel + (hz / 1000.0).ln() / log_step
+    } else {
+        hz * 3.0 / 200.0
+    }
+}
+
+fn mel_to_hz(mel: f64) -> f64 {
+    let min_log_mel = 15.0;
+    let log_step = 6.4f64.ln() / 27.0;
+    if mel >= min_log_mel {
+        1000.0 * (log_step * (mel - min_log_mel)).exp()
+    } else {
+        mel * 200.0 / 3.0
+    }
+}
+
+/// Triangular mel filters normalized like `librosa.filters.mel`, laid out as `[mel_bins, N_FFT / 2 + 1]`.
+fn mel_filters(mel_bins: usize) -> Vec<f32> {
+    let n_bins = N_FFT / 2 + 1;
+    let max_mel = hz_to_mel(SAMPLE_RATE as f64 / 2.0);
+    let points: Vec<f64> = (0..mel_bins + 2)
+        .map(|i| mel_to_hz(max_mel * i as f64 / (mel_bins + 1) as f64))
+        .collect();
+
+    let mut filters = vec![0.0f32; mel_bins * n_bins];
+    for m in 0..mel_bins {
+        let (lower, center, upper) = (points[m], points[m + 1], points[m + 2]);
+        let norm = 2.0 / (upper - lower);
+        for bin in 0..n_bins {
+            let hz = bin as f64 * SAMPLE_RATE as f64 / N_FFT as f64;
+            let rising = (hz - lower) / (center - lower);
+            let falling = (upper - hz) / (upper - center);
+            filters[m * n_bins + bin] = (rising.min(falling).max(0.0) * norm) as f32;
+        }
+    }
+    filters
+}
+
+/// Turns Whisper's byte-level BPE tokens back into text.
+struct Tokenizer {
+    tokens: HashMap<i64, String>,
+    ids: HashMap<String, i64>,
+    bytes: HashMap<char, u8>,
+    end_of_text: i64,
+    start_of_transcript: i64,
+    transcribe: i64,
+    no_timestamps: i64,
+}
+
+impl Tokenizer {
+    fn load(dir: &Path) -> Result<Self, String> {
+        let read = |file: &str| -> Result<HashMap<String, i64>, String> {
+            let path = dir.join(file);
+            let content = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read `{}`: {}", path.display(), e))?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid `{}`: {}", path.display(), e))
+        };
+
+        let mut ids = read("vocab.json")?;
+        // Older exports keep the special tokens in the vocabulary only
+        if let Ok(added) = read("added_tokens.json") {
+            ids.extend(added);
+        }
+
+        let special = |token: &str| {
+            ids.get(token)
+                .copied()
+                .ok_or_else(|| format!("Whisper vocabulary is missing `{}`.", token))
+        };
+
+        Ok(Self {
+            end_of_text: special("<|endoftext|>")?,
+            start_of_transcript: special("<|startoftranscript|>")?,
+            transcribe: special("<|transcribe|>")?,
+            no_timestamps: special("<|notimestamps|>")?,
+            tokens: ids.iter().map(|(token, id)| (*id, token.clone())).collect(),
+            bytes: byte_decoder(),
+            ids,
+        })
+    }
+
+    fn language(&self, language: Language) -> Result<i64, String> {
+        let token = match language {
+            Language::German => "<|de|>",
+            Language::English => "<|en|>",
+        };
+        self.ids
+            .get(token)
+            .copied()
+            .ok_or_else(|| format!("Whisper vocabulary is missing `{}`.", token))
+    }
+
+    fn decode(&self, ids: &[i64]) -> String {
+        let bytes: Vec<u8> = ids
+            .iter()
+            // Everything from `<|endoftext|>` on is a special token
+            .filter(|id| **id < self.end_of_text)
+            .filter_map(|id| self.tokens.get(id))
+            .flat_map(|token| token.chars())
+            .filter_map(|ch| self.bytes.get(&ch).copied())
+            .collect();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+/// GPT-2's mapping of printable characters back to the bytes they stand for.
+fn byte_decoder() -> HashMap<char, u8> {
+    let printable = |byte: u8| matches!(byte, b'!'..=b'~' | 0xa1..=0xac | 0xae..=0xff);
+
+    let mut shifted = 0;
+    (0..=255u8)
+        .map(|byte| {
+            let ch = if printable(byte) {
+                char::from(byte)
+            } else {
+                shifted += 1;
+                char::from_u32(255 + shifted).expect("Shifted bytes are valid characters")
+            };
+            (ch, byte)
+        })
+        .collect()
+}
@@ -0,0 +1,285 @@
+//! Keyword and regex rules per guild that answer matching messages.
+
+use crate::settings::SettingsStore;
+use crate::soundboard;
+use poise::serenity_prelude as serenity;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, ReactionType};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+pub const MAX_TRIGGERS_PER_GUILD: usize = 25;
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+/// Rules firing for a single message, so one message can't cause a flood.
+pub const MAX_ACTIONS_PER_MESSAGE: usize = 3;
+/// Keeps user supplied patterns from compiling into huge automatons.
+const MAX_REGEX_SIZE: usize = 1 << 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternKind {
+    /// Matches the words anywhere in the message, ignoring case
+    Keyword,
+    Regex,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize, poise::ChoiceParameter)]
+#[serde(rename_all = "lowercase")]
+pub enum ActionKind {
+    /// Reply with text
+    Reply,
+    /// Reply with a sound from the soundboard
+    Sound,
+    /// React with an emoji
+    React,
+    /// Speak text in the author's voice channel
+    Speak,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TriggerRule {
+    pub id: u32,
+    pub kind: PatternKind,
+    pub pattern: String,
+    pub action: ActionKind,
+    /// Reply text, sound name, emoji or text to speak, depending on the action.
+    pub value: String,
+    pub cooldown_secs: u64,
+}
+
+/// Sound shipped with the bot, usable in rules without uploading it first.
+pub const BUILTIN_SOUND: &str = "grrr";
+
+/// File of the sound called `name`, from the guild's soundboard or the built-in one.
+pub async fn sound_path(guild_id: GuildId, name: &str) -> Option<PathBuf> {
+    if let Some(path) = soundboard::find(guild_id, name).await {
+        return Some(path);
+    }
+    if name != BUILTIN_SOUND {
+        return None;
+    }
+    ["/app/grrr.mp3", "grrr.mp3"]
+        .into_iter()
+        .map(PathBuf::from)
+        .find(|path| path.exists())
+}
+
+/// Parses a unicode emoji or a custom one like `<:name:id>`.
+pub fn parse_emoji(emoji: &str) -> Option<ReactionType> {
+    let emoji = emoji.trim();
+    if emojis::get(emoji).is_some() {
+        return Some(ReactionType::Unicode(emoji.to_string()));
+    }
+    if emoji.starts_with('<') {
+        return ReactionType::try_from(emoji).ok();
+    }
+    None
+}
+
+/// Compiles a pattern, keywords match case-insensitively and never inside of other words.
+pub fn compile(kind: PatternKind, pattern: &str) -> Result<Regex, String> {
+    let source = match kind {
+        PatternKind::Keyword => {
+            let keyword = pattern.trim();
+            if keyword.is_empty() {
+                return Err("Keywords must not be empty.".to_string());
+            }
+            format!(r"(?:^|\W){}(?:\W|$)", regex::escape(keyword))
+        }
+        PatternKind::Regex => pattern.to_string(),
+    };
+
+    RegexBuilder::new(&source)
+        .case_insensitive(true)
+        .size_limit(MAX_REGEX_SIZE)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+/// The compiled rules of a guild.
+pub struct Matcher {
+    rules: Vec<(Regex, TriggerRule)>,
+}
+
+impl Matcher {
+    /// Compiles `rules`, skipping the ones that don't compile anymore.
+    pub fn new(rules: &[TriggerRule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter_map(|rule| match compile(rule.kind, &rule.pattern) {
+                Ok(regex) => Some((regex, rule.clone())),
+                Err(e) => {
+                    warn!("Skipping trigger {}: {}", rule.id, e);
+                    None
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Rules matching `content`, in the order they were added.
+    pub fn matches<'a>(&'a self, content: &'a str) -> impl Iterator<Item = &'a TriggerRule> + 'a {
+        self.rules
+            .iter()
+            .filter(move |(regex, _)| regex.is_match(content))
+            .map(|(_, rule)| rule)
+    }
+}
+
+/// When each rule fired last.
+#[derive(Default)]
+pub struct Cooldowns {
+    last_fired: HashMap<(GuildId, u32), Instant>,
+}
+
+impl Cooldowns {
+    /// Returns whether `rule` may fire at `now`, and if so starts its cooldown.
+    pub fn try_fire(&mut self, guild_id: GuildId, rule: &TriggerRule, now: Instant) -> bool {
+        let cooldown = Duration::from_secs(rule.cooldown_secs);
+        let key = (guild_id, rule.id);
+        if self
+            .last_fired
+            .get(&key)
+            .is_some_and(|last| now.duration_since(*last) < cooldown)
+        {
+            return false;
+        }
+        self.last_fired.insert(key, now);
+        true
+    }
+
+    /// Forgets the cooldown of a removed rule, so a new rule with its id starts fresh.
+    pub fn reset(&mut self, guild_id: GuildId, id: u32) {
+        self.last_fired.remove(&(guild_id, id));
+    }
+}
+
+/// Compiled matchers of all guilds, rebuilt from the settings after a change.
+#[derive(Default)]
+pub struct Triggers {
+    matchers: Mutex<HashMap<GuildId, Arc<Matcher>>>,
+    cooldowns: Mutex<Cooldowns>,
+}
+
+impl Triggers {
+    pub async fn matcher(&self, settings: &SettingsStore, guild_id: GuildId) -> Arc<Matcher> {
+        if let Some(matcher) = self.matchers.lock().unwrap().get(&guild_id) {
+            return matcher.clone();
+        }
+
+        let matcher = Arc::new(Matcher::new(&settings.guild(guild_id).await.triggers));
+        self.matchers
+            .lock()
+            .unwrap()
+            .insert(guild_id, matcher.clone());
+        matcher
+    }
+
+    /// Drops the compiled rules of the guild, call after changing them.
+    pub fn invalidate(&self, guild_id: GuildId, removed: Option<u32>) {
+        self.matchers.lock().unwrap().remove(&guild_id);
+        if let Some(id) = removed {
+            self.cooldowns.lock().unwrap().reset(guild_id, id);
+        }
+    }
+
+    pub fn try_fire(&self, guild_id: GuildId, rule: &TriggerRule) -> bool {
+        self.cooldowns
+            .lock()
+            .unwrap()
+            .try_fire(guild_id, rule, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: u32, kind: PatternKind, pattern: &str) -> TriggerRule {
+        TriggerRule {
+            id,
+            kind,
+            pattern: pattern.to_string(),
+            action: ActionKind::Reply,
+            value: "hi".to_string(),
+            cooldown_secs: 30,
+        }
+    }
+
+    fn matched(matcher: &Matcher, content: &str) -> Vec<u32> {
+        matcher.matches(content).map(|rule| rule.id).collect()
+    }
+
+    #[test]
+    fn keywords_match_whole_words_ignoring_case() {
+        let matcher = Matcher::new(&[rule(1, PatternKind::Keyword, "hello")]);
+        assert_eq!(matched(&matcher, "Hello there"), vec![1]);
+        assert_eq!(matched(&matcher, "well, HELLO!"), vec![1]);
+        assert!(matched(&matcher, "othello").is_empty());
+        assert!(matched(&matcher, "hellooo").is_empty());
+    }
+
+    #[test]
+    fn keywords_are_literal() {
+        let matcher = Matcher::new(&[
+            rule(1, PatternKind::Keyword, "c++"),
+            rule(2, PatternKind::Keyword, "good night"),
+        ]);
+        assert_eq!(matched(&matcher, "I love c++ a lot"), vec![1]);
+        assert!(matched(&matcher, "I love c a lot").is_empty());
+        assert_eq!(matched(&matcher, "Good night everyone"), vec![2]);
+        assert!(matched(&matcher, "good  night").is_empty());
+    }
+
+    #[test]
+    fn regex_rules() {
+        let matcher = Matcher::new(&[rule(1, PatternKind::Regex, r"^gr+$")]);
+        assert_eq!(matched(&matcher, "grrrr"), vec![1]);
+        assert_eq!(matched(&matcher, "GRR"), vec![1]);
+        assert!(matched(&matcher, "grrr!").is_empty());
+    }
+
+    #[test]
+    fn all_matching_rules_in_order() {
+        let matcher = Matcher::new(&[
+            rule(1, PatternKind::Keyword, "music"),
+            rule(2, PatternKind::Regex, "mus"),
+            rule(3, PatternKind::Keyword, "nope"),
+        ]);
+        assert_eq!(matched(&matcher, "play some music"), vec![1, 2]);
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected_and_skipped() {
+        assert!(compile(PatternKind::Regex, "(unclosed").is_err());
+        assert!(compile(PatternKind::Keyword, "   ").is_err());
+        assert!(compile(PatternKind::Regex, r"(?:\w{100}){100}").is_err());
+
+        let matcher = Matcher::new(&[
+            rule(1, PatternKind::Regex, "(unclosed"),
+            rule(2, PatternKind::Keyword, "fine"),
+        ]);
+        assert_eq!(matched(&matcher, "all fine"), vec![2]);
+    }
+
+    #[test]
+    fn cooldowns() {
+        let guild_id = GuildId::new(1);
+        let other_guild_id = GuildId::new(2);
+        let rule = rule(1, PatternKind::Keyword, "hi");
+        let start = Instant::now();
+        let mut cooldowns = Cooldowns::default();
+
+        assert!(cooldowns.try_fire(guild_id, &rule, start));
+        assert!(!cooldowns.try_fire(guild_id, &rule, start + Duration::from_secs(29)));
+        assert!(cooldowns.try_fire(other_guild_id, &rule, start + Duration::from_secs(29)));
+        assert!(cooldowns.try_fire(guild_id, &rule, start + Duration::from_secs(30)));
+
+        cooldowns.reset(guild_id, rule.id);
+        assert!(cooldowns.try_fire(guild_id, &rule, start + Duration::from_secs(31)));
+    }
+}
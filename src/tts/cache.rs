@@ -0,0 +1,192 @@
+//! Synthesized speech on disk, keyed by a hash of the voice and the segments spoken.
+//! Files are touched on every hit, the least recently used ones are evicted once the
+//! cache grows beyond `TTS_CACHE_MAX_MB`.
+
+use super::markup::Segment;
+use super::{decode_wav, encode_wav};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use tracing::warn;
+
+const CACHE_DIR: &str = "models/tts_cache";
+const DEFAULT_MAX_SIZE_MB: u64 = 256;
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+/// Numbers the temporary files, so concurrent stores of one key don't write to the same file.
+static STORES: AtomicU64 = AtomicU64::new(0);
+
+pub struct CacheStats {
+    pub files: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Maximum size of the cache in bytes, 0 disables it.
+fn max_bytes() -> u64 {
+    env::var("TTS_CACHE_MAX_MB")
+        .ok()
+        .and_then(|mb| mb.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE_MB)
+        * 1024
+        * 1024
+}
+
+/// Hashes everything that changes the synthesized audio.
+pub fn key(voice: &str, default_speaker: Option<i64>, segments: &[Segment]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(voice.as_bytes());
+    hasher.update([0]);
+
+    for segment in segments {
+        match segment {
+            Segment::Speech {
+                text,
+                prosody,
+                speaker,
+            } => {
+                hasher.update(format!(
+                    "speech {:?} {:?} {:?} {:?}\0",
+                    prosody.rate,
+                    prosody.pitch,
+                    prosody.volume,
+                    speaker.or(default_speaker)
+                ));
+                hasher.update(text.as_bytes());
+                hasher.update([0]);
+            }
+            Segment::Pause(duration) => hasher.update(format!("pause {}\0", duration.as_millis())),
+        }
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn path(key: &str) -> PathBuf {
+    Path::new(CACHE_DIR).join(format!("{}.wav", key))
+}
+
+fn is_cache_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "wav")
+}
+
+/// Returns the cached samples of `key` if they were synthesized at `sample_rate`.
+pub fn load(key: &str, sample_rate: u32) -> Option<Vec<f32>> {
+    if max_bytes() == 0 {
+        return None;
+    }
+
+    let path = path(key);
+    let samples = fs::read(&path)
+        .ok()
+        .and_then(|wav| decode_wav(&wav))
+        .filter(|(_, rate)| *rate == sample_rate)
+        .map(|(samples, _)| samples);
+
+    match samples {
+        Some(samples) => {
+            HITS.fetch_add(1, Ordering::Relaxed);
+            // The modification time doubles as the last use for eviction
+            if let Ok(file) = File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            Some(samples)
+        }
+        None => {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
+/// Stores the samples of `key`, then evicts old files if the cache got too big.
+pub fn store(key: &str, samples: &[f32], sample_rate: u32) {
+    let max_bytes = max_bytes();
+    if max_bytes == 0 {
+        return;
+    }
+
+    let path = path(key);
+    // Written to a temporary file first, so a concurrent load never sees half a file
+    let temp_path = path.with_extension(format!(
+        "{}-{}.tmp",
+        std::process::id(),
+        STORES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = fs::create_dir_all(CACHE_DIR)
+        .and_then(|_| fs::write(&temp_path, encode_wav(samples, sample_rate)))
+        .and_then(|_| fs::rename(&temp_path, &path));
+    if let Err(e) = result {
+        warn!("Failed to cache TTS audio: {}", e);
+        let _ = fs::remove_file(&temp_path);
+        return;
+    }
+
+    if let Err(e) = evict(max_bytes) {
+        warn!("Failed to evict TTS cache: {}", e);
+    }
+}
+
+fn entries() -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let entries = match fs::read_dir(CACHE_DIR) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    Ok(entries
+        .flatten()
+        .filter(|entry| is_cache_file(&entry.path()))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), modified))
+        })
+        .collect())
+}
+
+/// Deletes the least recently used files until the cache fits into `max_bytes`.
+fn evict(max_bytes: u64) -> io::Result<()> {
+    let mut entries = entries()?;
+    let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    if total <= max_bytes {
+        return Ok(());
+    }
+
+    entries.sort_by_key(|(_, _, modified)| *modified);
+    for (path, len, _) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(&path)?;
+        total = total.saturating_sub(len);
+    }
+    Ok(())
+}
+
+pub fn stats() -> io::Result<CacheStats> {
+    let entries = entries()?;
+    Ok(CacheStats {
+        files: entries.len(),
+        bytes: entries.iter().map(|(_, len, _)| len).sum(),
+        max_bytes: max_bytes(),
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+    })
+}
+
+/// Deletes every cached file, returns how many were deleted.
+pub fn purge() -> io::Result<usize> {
+    let entries = entries()?;
+    for (path, _, _) in &entries {
+        fs::remove_file(path)?;
+    }
+    Ok(entries.len())
+}
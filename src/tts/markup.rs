@@ -0,0 +1,382 @@
+//! A small inline markup for `/say`, e.g. `Hello [pause 500ms] [slow]world[/slow]`.
+//!
+//! Supported tags:
+//! - `[pause]`, `[pause 500ms]`, `[pause 1.5s]`: silence
+//! - `[slow]`/`[fast]`, `[loud]`/`[quiet]`, `[high]`/`[low]`: change rate, volume or pitch until the closing tag
+//! - `[speaker 2]...[/speaker]`: switch the speaker of multi-speaker voices
+//!
+//! Anything else in brackets is spoken as is.
+
+use super::split_sentences;
+use std::time::Duration;
+
+/// Rate used when nobody asked for a different one.
+pub const DEFAULT_RATE: u8 = 7;
+const SLOW_RATE: u8 = 2;
+const FAST_RATE: u8 = 15;
+const LOUD_VOLUME: u8 = 100;
+const QUIET_VOLUME: u8 = 30;
+const HIGH_PITCH: u8 = 75;
+const LOW_PITCH: u8 = 25;
+
+const DEFAULT_PAUSE: Duration = Duration::from_millis(500);
+pub const MAX_PAUSE: Duration = Duration::from_secs(10);
+
+/// Rate, pitch and volume in percent, as understood by piper. `None` keeps the voice's default.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Prosody {
+    pub rate: Option<u8>,
+    pub pitch: Option<u8>,
+    pub volume: Option<u8>,
+}
+
+impl Default for Prosody {
+    fn default() -> Self {
+        Self {
+            rate: Some(DEFAULT_RATE),
+            pitch: None,
+            volume: None,
+        }
+    }
+}
+
+/// The base settings markup is applied on top of.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpeechOptions {
+    pub prosody: Prosody,
+    /// Silence inserted between sentences.
+    pub sentence_pause: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Segment {
+    /// A single sentence, synthesized in one go.
+    Speech {
+        text: String,
+        prosody: Prosody,
+        speaker: Option<i64>,
+    },
+    Pause(Duration),
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    prosody: Prosody,
+    speaker: Option<i64>,
+}
+
+enum Tag {
+    Pause(Duration),
+    Open(&'static str, State),
+    Close(String),
+}
+
+/// Turns `text` into segments. Plain text without markup yields one segment per sentence.
+pub fn parse(text: &str, options: &SpeechOptions) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut state = State {
+        prosody: options.prosody,
+        speaker: None,
+    };
+    // Open tags and the state to return to once they are closed
+    let mut open: Vec<(&'static str, State)> = Vec::new();
+    let mut run = String::new();
+
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + len];
+
+        let Some(tag) = parse_tag(tag, state) else {
+            // Not markup, keep the text including the opening bracket and look further
+            run.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        };
+
+        run.push_str(&rest[..start]);
+        rest = &rest[start + len + 1..];
+
+        flush(&mut segments, &mut run, state, options);
+        match tag {
+            Tag::Pause(duration) => segments.push(Segment::Pause(duration)),
+            Tag::Open(name, new_state) => {
+                open.push((name, state));
+                state = new_state;
+            }
+            Tag::Close(name) => {
+                // Closing an outer tag also closes everything opened inside of it
+                if let Some(index) = open.iter().rposition(|(open, _)| *open == name) {
+                    state = open[index].1;
+                    open.truncate(index);
+                }
+            }
+        }
+    }
+
+    run.push_str(rest);
+    flush(&mut segments, &mut run, state, options);
+
+    segments
+}
+
+fn parse_tag(tag: &str, state: State) -> Option<Tag> {
+    let tag = tag.trim().to_lowercase();
+    if let Some(name) = tag.strip_prefix('/') {
+        return match name.trim() {
+            name @ ("slow" | "fast" | "loud" | "quiet" | "high" | "low" | "speaker") => {
+                Some(Tag::Close(name.to_string()))
+            }
+            _ => None,
+        };
+    }
+
+    let (name, argument) = match tag.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, Some(argument.trim())),
+        None => (tag.as_str(), None),
+    };
+
+    let mut new_state = state;
+    let name = match (name, argument) {
+        ("pause", None) => return Some(Tag::Pause(DEFAULT_PAUSE)),
+        ("pause", Some(duration)) => return parse_duration(duration).map(Tag::Pause),
+        ("speaker", Some(speaker)) => {
+            new_state.speaker = Some(speaker.parse().ok()?);
+            "speaker"
+        }
+        ("slow", None) => {
+            new_state.prosody.rate = Some(SLOW_RATE);
+            "slow"
+        }
+        ("fast", None) => {
+            new_state.prosody.rate = Some(FAST_RATE);
+            "fast"
+        }
+        ("loud", None) => {
+            new_state.prosody.volume = Some(LOUD_VOLUME);
+            "loud"
+        }
+        ("quiet", None) => {
+            new_state.prosody.volume = Some(QUIET_VOLUME);
+            "quiet"
+        }
+        ("high", None) => {
+            new_state.prosody.pitch = Some(HIGH_PITCH);
+            "high"
+        }
+        ("low", None) => {
+            new_state.prosody.pitch = Some(LOW_PITCH);
+            "low"
+        }
+        _ => return None,
+    };
+
+    Some(Tag::Open(name, new_state))
+}
+
+/// Parses `500ms`, `1.5s` or a bare number of milliseconds, capped at [`MAX_PAUSE`].
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = if let Some(ms) = duration.strip_suffix("ms") {
+        Duration::from_millis(ms.trim().parse().ok()?)
+    } else if let Some(secs) = duration.strip_suffix('s') {
+        Duration::try_from_secs_f64(secs.trim().parse().ok()?).ok()?
+    } else {
+        Duration::from_millis(duration.parse().ok()?)
+    };
+
+    Some(duration.min(MAX_PAUSE))
+}
+
+/// Moves the text collected so far into sentence segments.
+fn flush(segments: &mut Vec<Segment>, run: &mut String, state: State, options: &SpeechOptions) {
+    let sentences = split_sentences(run);
+    run.clear();
+
+    let count = sentences.len();
+    for (i, text) in sentences.into_iter().enumerate() {
+        segments.push(Segment::Speech {
+            text,
+            prosody: state.prosody,
+            speaker: state.speaker,
+        });
+        if i + 1 < count && !options.sentence_pause.is_zero() {
+            segments.push(Segment::Pause(options.sentence_pause));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn speech(text: &str, prosody: Prosody, speaker: Option<i64>) -> Segment {
+        Segment::Speech {
+            text: text.to_string(),
+            prosody,
+            speaker,
+        }
+    }
+
+    fn plain(text: &str) -> Segment {
+        speech(text, Prosody::default(), None)
+    }
+
+    #[test]
+    fn plain_text_is_split_into_sentences() {
+        let segments = parse("Hello there. How are you?", &SpeechOptions::default());
+        assert_eq!(segments, vec![plain("Hello there."), plain("How are you?")]);
+    }
+
+    #[test]
+    fn pauses() {
+        let segments = parse(
+            "a [pause] b [pause 250ms] c [pause 1.5s] d [pause 300] e [pause 60s]",
+            &SpeechOptions::default(),
+        );
+        assert_eq!(
+            segments,
+            vec![
+                plain("a"),
+                Segment::Pause(DEFAULT_PAUSE),
+                plain("b"),
+                Segment::Pause(Duration::from_millis(250)),
+                plain("c"),
+                Segment::Pause(Duration::from_millis(1500)),
+                plain("d"),
+                Segment::Pause(Duration::from_millis(300)),
+                plain("e"),
+                Segment::Pause(MAX_PAUSE),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_pause_is_spoken() {
+        let segments = parse("wait [pause soon] now", &SpeechOptions::default());
+        assert_eq!(segments, vec![plain("wait [pause soon] now")]);
+    }
+
+    #[test]
+    fn prosody_tags_nest_and_close() {
+        let segments = parse(
+            "normal [slow]slow [loud]slow and loud[/loud] slow again[/slow] normal",
+            &SpeechOptions::default(),
+        );
+
+        let slow = Prosody {
+            rate: Some(SLOW_RATE),
+            ..Prosody::default()
+        };
+        let slow_loud = Prosody {
+            volume: Some(LOUD_VOLUME),
+            ..slow
+        };
+        assert_eq!(
+            segments,
+            vec![
+                plain("normal"),
+                speech("slow", slow, None),
+                speech("slow and loud", slow_loud, None),
+                speech("slow again", slow, None),
+                plain("normal"),
+            ]
+        );
+    }
+
+    #[test]
+    fn closing_outer_tag_closes_inner_ones() {
+        let segments = parse("[fast]a [high]b[/fast] c", &SpeechOptions::default());
+        let fast = Prosody {
+            rate: Some(FAST_RATE),
+            ..Prosody::default()
+        };
+        let fast_high = Prosody {
+            pitch: Some(HIGH_PITCH),
+            ..fast
+        };
+        assert_eq!(
+            segments,
+            vec![
+                speech("a", fast, None),
+                speech("b", fast_high, None),
+                plain("c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmatched_closing_tag_is_ignored() {
+        let segments = parse("a [/slow] b", &SpeechOptions::default());
+        assert_eq!(segments, vec![plain("a"), plain("b")]);
+    }
+
+    #[test]
+    fn speaker_switch() {
+        let segments = parse(
+            "[speaker 3]Hi.[/speaker] Bye. [speaker x]",
+            &SpeechOptions::default(),
+        );
+        assert_eq!(
+            segments,
+            vec![
+                speech("Hi.", Prosody::default(), Some(3)),
+                plain("Bye."),
+                plain("[speaker x]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_and_unclosed_brackets_are_spoken() {
+        let segments = parse("[citation needed] see [1] [slow", &SpeechOptions::default());
+        assert_eq!(segments, vec![plain("[citation needed] see [1] [slow")]);
+    }
+
+    #[test]
+    fn tags_are_case_insensitive() {
+        let segments = parse("[SLOW]a[/Slow]", &SpeechOptions::default());
+        assert_eq!(
+            segments,
+            vec![speech(
+                "a",
+                Prosody {
+                    rate: Some(SLOW_RATE),
+                    ..Prosody::default()
+                },
+                None
+            )]
+        );
+    }
+
+    #[test]
+    fn options_are_the_base() {
+        let options = SpeechOptions {
+            prosody: Prosody {
+                rate: Some(20),
+                pitch: Some(60),
+                volume: None,
+            },
+            sentence_pause: Duration::from_millis(200),
+        };
+        let segments = parse("One. Two. [quiet]Three.", &options);
+
+        assert_eq!(
+            segments,
+            vec![
+                speech("One.", options.prosody, None),
+                Segment::Pause(Duration::from_millis(200)),
+                speech("Two.", options.prosody, None),
+                speech(
+                    "Three.",
+                    Prosody {
+                        volume: Some(QUIET_VOLUME),
+                        ..options.prosody
+                    },
+                    None
+                ),
+            ]
+        );
+    }
+}
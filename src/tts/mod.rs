@@ -0,0 +1,260 @@
+pub mod cache;
+pub mod markup;
+pub mod normalize;
+pub mod queue;
+pub mod voices;
+
+use markup::{Prosody, Segment};
+use piper_rs::synth::AudioOutputConfig;
+use songbird::input::{Input, RawAdapter};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use symphonia::core::io::MediaSource;
+use tokio::task::{self, JoinHandle};
+
+/// Silence played while the next sentence is still being synthesized.
+const UNDERRUN_SILENCE_MS: usize = 20;
+
+/// Speech that is synthesized sentence by sentence while it is already playing.
+pub struct Speech {
+    /// Mono `f32` PCM, starts as soon as the first sentence is ready.
+    pub input: Input,
+    pub audio: SpeechAudio,
+}
+
+/// The whole utterance, available once synthesis has finished.
+pub struct SpeechAudio {
+    samples: JoinHandle<Result<Vec<f32>, String>>,
+    sample_rate: u32,
+}
+
+fn output_config(prosody: Prosody) -> AudioOutputConfig {
+    AudioOutputConfig {
+        rate: prosody.rate,
+        volume: prosody.volume,
+        pitch: prosody.pitch,
+        appended_silence_ms: None,
+    }
+}
+
+/// Splits text at sentence ends, so the first sentence can be spoken before the rest is synthesized.
+pub(crate) fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        current.push(ch);
+
+        let at_boundary = ch == '\n'
+            || (matches!(ch, '.' | '!' | '?' | ';')
+                && chars.peek().is_none_or(|next| next.is_whitespace()));
+        if at_boundary {
+            let sentence = current.trim();
+            if !sentence.is_empty() {
+                sentences.push(sentence.to_string());
+            }
+            current.clear();
+        }
+    }
+
+    let rest = current.trim();
+    if !rest.is_empty() {
+        sentences.push(rest.to_string());
+    }
+
+    sentences
+}
+
+/// Starts synthesizing plain `text` with `voice` in the background and returns it as a playable input.
+pub async fn synthesize(text: &str, voice: &str) -> Result<Speech, String> {
+    let segments = split_sentences(text)
+        .into_iter()
+        .map(|text| Segment::Speech {
+            text,
+            prosody: Prosody::default(),
+            speaker: None,
+        })
+        .collect();
+    synthesize_segments(segments, voice).await
+}
+
+/// Like [`synthesize`], but for text that was already parsed with [`markup::parse`].
+pub async fn synthesize_segments(segments: Vec<Segment>, voice: &str) -> Result<Speech, String> {
+    let voice_name = voice;
+    let voice = voices::get(voice_name).await?;
+    let sample_rate = voice.sample_rate;
+
+    let (chunk_tx, chunk_rx) = mpsc::channel();
+
+    let key = cache::key(voice_name, voice.default_speaker(), &segments);
+    let cached = task::spawn_blocking({
+        let key = key.clone();
+        move || cache::load(&key, sample_rate)
+    })
+    .await
+    .ok()
+    .flatten();
+
+    if let Some(samples) = cached {
+        let _ = chunk_tx.send(samples.clone());
+        drop(chunk_tx);
+        return Ok(Speech {
+            input: pcm_input(chunk_rx, sample_rate),
+            audio: SpeechAudio {
+                samples: task::spawn(async move { Ok(samples) }),
+                sample_rate,
+            },
+        });
+    }
+
+    let samples = task::spawn_blocking(move || {
+        let mut all_samples = Vec::new();
+        for segment in segments {
+            let mut sentence_samples = match segment {
+                Segment::Speech {
+                    text,
+                    prosody,
+                    speaker,
+                } => voice.with_speaker(speaker, || {
+                    let audio = voice
+                        .synth
+                        .synthesize_parallel(text, Some(output_config(prosody)))
+                        .map_err(|e| format!("Synthesis failed: {}", e))?;
+
+                    let mut samples = Vec::new();
+                    for part in audio {
+                        let part = part.map_err(|e| format!("Synthesis failed: {}", e))?;
+                        samples.append(&mut part.into_vec());
+                    }
+                    Ok::<_, String>(samples)
+                })?,
+                Segment::Pause(duration) => {
+                    vec![0.0; (duration.as_secs_f64() * sample_rate as f64) as usize]
+                }
+            };
+
+            // Playback may already be gone, keep going so the caller still gets the whole audio
+            let _ = chunk_tx.send(sentence_samples.clone());
+            all_samples.append(&mut sentence_samples);
+        }
+
+        cache::store(&key, &all_samples, sample_rate);
+        Ok(all_samples)
+    });
+
+    Ok(Speech {
+        input: pcm_input(chunk_rx, sample_rate),
+        audio: SpeechAudio {
+            samples,
+            sample_rate,
+        },
+    })
+}
+
+fn pcm_input(chunks: Receiver<Vec<f32>>, sample_rate: u32) -> Input {
+    let stream = PcmStream {
+        chunks: std::sync::Mutex::new(chunks),
+        pending: Vec::new(),
+        offset: 0,
+        silence_bytes: sample_rate as usize * UNDERRUN_SILENCE_MS / 1000 * 4,
+    };
+    RawAdapter::new(stream, sample_rate, 1).into()
+}
+
+impl SpeechAudio {
+    /// Waits for synthesis to finish and encodes the whole utterance as a WAV file.
+    pub async fn into_wav(self) -> Result<Vec<u8>, String> {
+        let samples = self
+            .samples
+            .await
+            .map_err(|e| format!("Synthesis task failed: {}", e))??;
+        Ok(encode_wav(&samples, self.sample_rate))
+    }
+}
+
+/// Encodes mono `f32` samples as a 16 bit PCM WAV file.
+pub fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+
+    wav
+}
+
+/// Decodes a WAV file written by [`encode_wav`] into samples and its sample rate.
+pub fn decode_wav(wav: &[u8]) -> Option<(Vec<f32>, u32)> {
+    if wav.len() < 44 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" || &wav[36..40] != b"data"
+    {
+        return None;
+    }
+
+    let sample_rate = u32::from_le_bytes(wav[24..28].try_into().ok()?);
+    let samples = wav[44..]
+        .chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]) as f32 / i16::MAX as f32)
+        .collect();
+    Some((samples, sample_rate))
+}
+
+/// Raw little endian `f32` PCM fed by the synthesis task, one sentence at a time.
+struct PcmStream {
+    chunks: std::sync::Mutex<Receiver<Vec<f32>>>,
+    pending: Vec<u8>,
+    offset: usize,
+    silence_bytes: usize,
+}
+
+impl Read for PcmStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.offset >= self.pending.len() {
+            let next = self.chunks.lock().unwrap().try_recv();
+            self.pending = match next {
+                Ok(samples) => samples.iter().flat_map(|s| s.to_le_bytes()).collect(),
+                // Synthesis is behind playback, play a little silence instead of stalling the mixer
+                Err(TryRecvError::Empty) => vec![0; self.silence_bytes],
+                Err(TryRecvError::Disconnected) => return Ok(0),
+            };
+            self.offset = 0;
+        }
+
+        let n = buf.len().min(self.pending.len() - self.offset);
+        buf[..n].copy_from_slice(&self.pending[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+impl Seek for PcmStream {
+    fn seek(&mut self, _pos: SeekFrom) -> io::Result<u64> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl MediaSource for PcmStream {
+    fn is_seekable(&self) -> bool {
+        false
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        None
+    }
+}
@@ -0,0 +1,916 @@
+//! Turns chat text into something a voice can read out: mentions become names, URLs their
+//! domain, numbers words, and markdown and emoji are dropped or named.
+//!
+//! Each step is a plain function on `&str`, [`normalize`] runs all of them in order.
+
+use crate::settings::SettingsStore;
+use poise::serenity_prelude as serenity;
+use serenity::all::{ChannelId, GuildId, RoleId, UserId};
+
+/// Numbers longer than this are read digit by digit.
+const MAX_NUMBER_DIGITS: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    German,
+    English,
+}
+
+impl Language {
+    /// Guesses the language from a piper voice name like `de_DE-thorsten-medium`.
+    pub fn of_voice(voice: &str) -> Self {
+        match voice.split(['_', '-']).next() {
+            Some("de") => Language::German,
+            _ => Language::English,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalizeOptions {
+    pub language: Language,
+    /// Read emoji by name instead of dropping them.
+    pub read_emoji: bool,
+}
+
+impl NormalizeOptions {
+    /// Options for speaking with `voice` in `guild_id`.
+    pub async fn for_voice(
+        settings: &SettingsStore,
+        guild_id: Option<GuildId>,
+        voice: &str,
+    ) -> Self {
+        let read_emoji = match guild_id {
+            Some(guild_id) => settings.guild(guild_id).await.read_emoji,
+            None => false,
+        };
+
+        Self {
+            language: Language::of_voice(voice),
+            read_emoji,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mention {
+    User(UserId),
+    Role(RoleId),
+    Channel(ChannelId),
+}
+
+/// Runs every step on `text`. `resolve` looks up the names of mentioned users, roles and channels.
+pub fn normalize(
+    text: &str,
+    options: &NormalizeOptions,
+    resolve: impl Fn(Mention) -> Option<String>,
+) -> String {
+    let text = mentions(text, resolve);
+    let text = urls(&text);
+    let text = markdown(&text);
+    let text = emoji(&text, options.read_emoji);
+    let text = numbers(&text, options.language);
+    collapse_whitespace(&text)
+}
+
+/// Looks up mentions in the serenity cache, using nicknames where possible.
+pub fn cache_resolver(
+    ctx: &serenity::Context,
+    guild_id: Option<GuildId>,
+) -> impl Fn(Mention) -> Option<String> + '_ {
+    move |mention| {
+        let guild = guild_id.and_then(|guild_id| ctx.cache.guild(guild_id));
+        match mention {
+            Mention::User(user_id) => guild
+                .as_ref()
+                .and_then(|guild| guild.members.get(&user_id))
+                .map(|member| member.display_name().to_string())
+                .or_else(|| {
+                    ctx.cache
+                        .user(user_id)
+                        .map(|user| user.display_name().to_string())
+                }),
+            Mention::Role(role_id) => guild?.roles.get(&role_id).map(|role| role.name.clone()),
+            Mention::Channel(channel_id) => guild?
+                .channels
+                .get(&channel_id)
+                .map(|channel| channel.name.clone()),
+        }
+    }
+}
+
+/// Replaces `<@id>`, `<@!id>`, `<@&id>` and `<#id>` with names. Unknown mentions are dropped.
+pub fn mentions(text: &str, resolve: impl Fn(Mention) -> Option<String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        let parsed = tail.find('>').and_then(|end| {
+            let inner = &tail[1..end];
+            let mention = if let Some(id) = inner.strip_prefix("@&") {
+                Mention::Role(RoleId::new(parse_id(id)?))
+            } else if let Some(id) = inner.strip_prefix("@!").or(inner.strip_prefix('@')) {
+                Mention::User(UserId::new(parse_id(id)?))
+            } else if let Some(id) = inner.strip_prefix('#') {
+                Mention::Channel(ChannelId::new(parse_id(id)?))
+            } else {
+                return None;
+            };
+            Some((mention, end))
+        });
+
+        match parsed {
+            Some((mention, end)) => {
+                if let Some(name) = resolve(mention) {
+                    result.push_str(&name);
+                }
+                rest = &tail[end + 1..];
+            }
+            None => {
+                result.push('<');
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn parse_id(id: &str) -> Option<u64> {
+    id.parse().ok().filter(|&id| id != 0)
+}
+
+/// Replaces URLs with their domain, `https://www.example.com/a?b` becomes `example.com`.
+pub fn urls(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = ["https://", "http://"]
+        .iter()
+        .filter_map(|scheme| rest.find(scheme))
+        .min()
+    {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let end = tail
+            .find(|ch: char| ch.is_whitespace() || matches!(ch, '<' | '>' | ')'))
+            .unwrap_or(tail.len());
+        // Punctuation after the URL belongs to the sentence
+        let url = tail[..end].trim_end_matches(['.', ',', '!', '?', ';', ':']);
+        let end = url.len();
+        let host = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['/', '?', '#'])
+            .next()
+            .unwrap_or_default();
+        // Drop credentials and ports
+        let host = host.rsplit('@').next().unwrap_or(host);
+        let host = host.split(':').next().unwrap_or(host);
+        let host = host.strip_prefix("www.").unwrap_or(host);
+        result.push_str(host);
+
+        rest = &tail[end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Drops markdown syntax. Code blocks are dropped entirely, links keep their text.
+pub fn markdown(text: &str) -> String {
+    let mut without_code = String::with_capacity(text.len());
+    for (i, part) in text.split("```").enumerate() {
+        // Every odd part is inside a code block
+        if i % 2 == 0 {
+            without_code.push_str(part);
+        }
+    }
+
+    let lines: Vec<String> = without_code
+        .lines()
+        .map(|line| {
+            let line = line.trim_start();
+            let line = line.trim_start_matches('#').trim_start();
+            let line = line.strip_prefix("> ").unwrap_or(line);
+            let line = ["- ", "* ", "+ "]
+                .iter()
+                .find_map(|bullet| line.strip_prefix(bullet))
+                .unwrap_or(line);
+            inline_markdown(&links(line))
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Replaces `[text](url)` with `text`.
+fn links(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('[') {
+        let tail = &rest[start..];
+        let link = tail.find("](").and_then(|middle| {
+            let end = middle + tail[middle..].find(')')?;
+            Some((&tail[1..middle], end))
+        });
+
+        match link {
+            Some((label, end)) if !label.contains('[') => {
+                result.push_str(&rest[..start]);
+                result.push_str(label);
+                rest = &tail[end + 1..];
+            }
+            _ => {
+                result.push_str(&rest[..=start]);
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Drops emphasis, strike-through, spoiler and inline code markers.
+fn inline_markdown(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = String::with_capacity(line.len());
+
+    for (i, &ch) in chars.iter().enumerate() {
+        let keep = match ch {
+            '*' | '`' | '~' | '|' => false,
+            // Keep underscores inside of words like snake_case
+            '_' => {
+                let before = i.checked_sub(1).and_then(|i| chars.get(i));
+                let after = chars.get(i + 1);
+                before.is_some_and(|c| c.is_alphanumeric())
+                    && after.is_some_and(|c| c.is_alphanumeric())
+            }
+            _ => true,
+        };
+        if keep {
+            result.push(ch);
+        }
+    }
+
+    result
+}
+
+fn is_emoji(ch: char) -> bool {
+    let code = ch as u32;
+    matches!(
+        code,
+        0x1F300..=0x1FAFF
+            | 0x1F1E6..=0x1F1FF
+            | 0x2600..=0x26FF
+            | 0x2700..=0x27BF
+            | 0xFE00..=0xFE0F
+            | 0x200D
+            | 0x20E3
+    )
+}
+
+/// Drops emoji, or replaces them with their name if `read` is set. Custom emoji like
+/// `<:pepe_hi:123>` are read as `pepe hi`.
+pub fn emoji(text: &str, read: bool) -> String {
+    let text = custom_emoji(text, read);
+    let mut result = String::with_capacity(text.len());
+    let mut run = String::new();
+
+    for ch in text.chars() {
+        if is_emoji(ch) {
+            run.push(ch);
+            continue;
+        }
+        if !run.is_empty() {
+            if read {
+                push_emoji_names(&mut result, &run);
+            }
+            run.clear();
+        }
+        result.push(ch);
+    }
+    if read && !run.is_empty() {
+        push_emoji_names(&mut result, &run);
+    }
+
+    result
+}
+
+/// Names a run of emoji characters, matching the longest known emoji first.
+fn push_emoji_names(result: &mut String, run: &str) {
+    let mut rest = run;
+    while let Some(first) = rest.chars().next() {
+        let found = rest
+            .char_indices()
+            .map(|(i, ch)| i + ch.len_utf8())
+            .rev()
+            .find_map(|end| emojis::get(&rest[..end]).map(|emoji| (emoji.name(), end)));
+
+        match found {
+            Some((name, end)) => {
+                result.push(' ');
+                result.push_str(name);
+                result.push(' ');
+                rest = &rest[end..];
+            }
+            // Modifiers and joiners on their own
+            None => rest = &rest[first.len_utf8()..],
+        }
+    }
+}
+
+fn custom_emoji(text: &str, read: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        let tail = &rest[start..];
+
+        let name = tail.find('>').and_then(|end| {
+            let inner = &tail[1..end];
+            let inner = inner.strip_prefix('a').unwrap_or(inner);
+            let (name, id) = inner.strip_prefix(':')?.split_once(':')?;
+            parse_id(id)?;
+            Some((name, end))
+        });
+
+        match name {
+            Some((name, end)) => {
+                if read {
+                    result.push(' ');
+                    result.push_str(&name.replace('_', " "));
+                    result.push(' ');
+                }
+                rest = &tail[end + 1..];
+            }
+            None => {
+                result.push('<');
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+struct Unit {
+    symbol: &'static str,
+    german: (&'static str, &'static str),
+    english: (&'static str, &'static str),
+    /// German `eine Stunde` instead of `ein Meter`
+    feminine: bool,
+}
+
+const fn unit(
+    symbol: &'static str,
+    german: (&'static str, &'static str),
+    english: (&'static str, &'static str),
+    feminine: bool,
+) -> Unit {
+    Unit {
+        symbol,
+        german,
+        english,
+        feminine,
+    }
+}
+
+/// Longer symbols first, so `km/h` wins over `km` and `ms` over `m`.
+const UNITS: &[Unit] = &[
+    unit(
+        "km/h",
+        ("Kilometer pro Stunde", "Kilometer pro Stunde"),
+        ("kilometer per hour", "kilometers per hour"),
+        false,
+    ),
+    unit(
+        "°C",
+        ("Grad Celsius", "Grad Celsius"),
+        ("degree Celsius", "degrees Celsius"),
+        false,
+    ),
+    unit(
+        "°F",
+        ("Grad Fahrenheit", "Grad Fahrenheit"),
+        ("degree Fahrenheit", "degrees Fahrenheit"),
+        false,
+    ),
+    unit("min", ("Minute", "Minuten"), ("minute", "minutes"), true),
+    unit(
+        "km",
+        ("Kilometer", "Kilometer"),
+        ("kilometer", "kilometers"),
+        false,
+    ),
+    unit(
+        "kg",
+        ("Kilogramm", "Kilogramm"),
+        ("kilogram", "kilograms"),
+        false,
+    ),
+    unit(
+        "cm",
+        ("Zentimeter", "Zentimeter"),
+        ("centimeter", "centimeters"),
+        false,
+    ),
+    unit(
+        "mm",
+        ("Millimeter", "Millimeter"),
+        ("millimeter", "millimeters"),
+        false,
+    ),
+    unit(
+        "ml",
+        ("Milliliter", "Milliliter"),
+        ("milliliter", "milliliters"),
+        false,
+    ),
+    unit(
+        "ms",
+        ("Millisekunde", "Millisekunden"),
+        ("millisecond", "milliseconds"),
+        true,
+    ),
+    unit(
+        "GB",
+        ("Gigabyte", "Gigabyte"),
+        ("gigabyte", "gigabytes"),
+        false,
+    ),
+    unit(
+        "MB",
+        ("Megabyte", "Megabyte"),
+        ("megabyte", "megabytes"),
+        false,
+    ),
+    unit("%", ("Prozent", "Prozent"), ("percent", "percent"), false),
+    unit("€", ("Euro", "Euro"), ("euro", "euros"), false),
+    unit("$", ("Dollar", "Dollar"), ("dollar", "dollars"), false),
+    unit("£", ("Pfund", "Pfund"), ("pound", "pounds"), false),
+    unit("m", ("Meter", "Meter"), ("meter", "meters"), false),
+    unit("g", ("Gramm", "Gramm"), ("gram", "grams"), false),
+    unit("l", ("Liter", "Liter"), ("liter", "liters"), false),
+    unit("h", ("Stunde", "Stunden"), ("hour", "hours"), true),
+    unit("s", ("Sekunde", "Sekunden"), ("second", "seconds"), true),
+];
+
+/// Currencies that may also be written in front of the number, like `$5`.
+const PREFIX_UNITS: &[&str] = &["€", "$", "£"];
+
+/// Spells out numbers, decimals and the unit following them in `language`.
+pub fn numbers(text: &str, language: Language) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find(|ch: char| ch.is_ascii_digit()) {
+        // Numbers glued to letters, like `mp3` or `4k`, are left alone
+        if rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|ch| ch.is_alphanumeric())
+        {
+            let len = rest[start..]
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(rest.len() - start);
+            result.push_str(&rest[..start + len]);
+            rest = &rest[start + len..];
+            continue;
+        }
+
+        let mut before = &rest[..start];
+        let prefix_unit = PREFIX_UNITS
+            .iter()
+            .find(|symbol| before.ends_with(**symbol))
+            .and_then(|symbol| UNITS.iter().find(|unit| unit.symbol == *symbol));
+        if let Some(unit) = prefix_unit {
+            before = &before[..before.len() - unit.symbol.len()];
+        }
+        result.push_str(before);
+
+        let tail = &rest[start..];
+        let integer_len = tail
+            .find(|ch: char| !ch.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let integer = &tail[..integer_len];
+        let mut end = integer_len;
+
+        // `3.5` and `3,5`, but not the full stop in `Seite 3.`
+        let mut fraction = None;
+        if let Some(separator) = tail[end..]
+            .chars()
+            .next()
+            .filter(|ch| matches!(ch, '.' | ','))
+        {
+            let after = &tail[end + 1..];
+            let len = after
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(after.len());
+            if len > 0 {
+                fraction = Some((separator, &after[..len]));
+                end += 1 + len;
+            }
+        }
+
+        let mut unit = prefix_unit;
+        if unit.is_none() {
+            let after = &tail[end..];
+            let trimmed = after.strip_prefix(' ').unwrap_or(after);
+            unit = UNITS.iter().find(|unit| {
+                trimmed
+                    .strip_prefix(unit.symbol)
+                    .is_some_and(|rest| !rest.chars().next().is_some_and(|ch| ch.is_alphanumeric()))
+            });
+            if let Some(unit) = unit {
+                end += after.len() - trimmed.len() + unit.symbol.len();
+            }
+        }
+
+        // Like `4k`, not a number we know how to read
+        if unit.is_none() && tail[end..].chars().next().is_some_and(char::is_alphabetic) {
+            result.push_str(&tail[..end]);
+            rest = &tail[end..];
+            continue;
+        }
+
+        let is_one = integer.trim_start_matches('0') == "1" && fraction.is_none();
+        let mut words = match (unit, language) {
+            (Some(unit), Language::German) if is_one => {
+                if unit.feminine { "eine" } else { "ein" }.to_string()
+            }
+            _ => spell_integer(integer, language),
+        };
+        if let Some((separator, digits)) = fraction {
+            // `1,000` in English and `1.000` in German are thousands, not decimals
+            let is_thousands = digits.len() == 3
+                && matches!(
+                    (separator, language),
+                    (',', Language::English) | ('.', Language::German)
+                );
+            if is_thousands {
+                words = spell_integer(&format!("{}{}", integer, digits), language);
+            } else {
+                words.push_str(match language {
+                    Language::German => " Komma",
+                    Language::English => " point",
+                });
+                for digit in digits.chars() {
+                    words.push(' ');
+                    words.push_str(&spell_integer(&digit.to_string(), language));
+                }
+            }
+        }
+        result.push_str(&words);
+
+        if let Some(unit) = unit {
+            let (singular, plural) = match language {
+                Language::German => unit.german,
+                Language::English => unit.english,
+            };
+            result.push(' ');
+            result.push_str(if is_one { singular } else { plural });
+        }
+
+        rest = &tail[end..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn spell_integer(digits: &str, language: Language) -> String {
+    let significant = digits.trim_start_matches('0');
+    let spell = match language {
+        Language::German => german_number,
+        Language::English => english_number,
+    };
+
+    // Leading zeros like in `007` and very long numbers are read digit by digit
+    if (digits.len() > 1 && digits.starts_with('0')) || significant.len() > MAX_NUMBER_DIGITS {
+        return digits
+            .chars()
+            .map(|digit| spell(digit.to_digit(10).unwrap_or_default() as u64))
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+
+    spell(significant.parse().unwrap_or(0))
+}
+
+fn german_number(n: u64) -> String {
+    if n == 0 {
+        return "null".to_string();
+    }
+    // `eins` at the end, `ein` inside of compounds like `einhundert`
+    let words = german_below_million(n % 1_000_000);
+    let words = if words.ends_with("ein") {
+        format!("{}s", words)
+    } else {
+        words
+    };
+
+    let mut large = Vec::new();
+    for (scale, singular, plural) in [
+        (1_000_000_000, "Milliarde", "Milliarden"),
+        (1_000_000, "Million", "Millionen"),
+    ] {
+        let count = n / scale % 1000;
+        match count {
+            0 => {}
+            1 => large.push(format!("eine {}", singular)),
+            count => large.push(format!("{} {}", german_below_million(count), plural)),
+        }
+    }
+
+    if !words.is_empty() {
+        large.push(words);
+    }
+    large.join(" ")
+}
+
+fn german_below_million(n: u64) -> String {
+    let thousands = n / 1000;
+    let rest = n % 1000;
+
+    let mut words = String::new();
+    if thousands > 0 {
+        words.push_str(&german_below_thousand(thousands));
+        words.push_str("tausend");
+    }
+    words.push_str(&german_below_thousand(rest));
+    words
+}
+
+fn german_below_thousand(n: u64) -> String {
+    const ONES: [&str; 20] = [
+        "",
+        "ein",
+        "zwei",
+        "drei",
+        "vier",
+        "fünf",
+        "sechs",
+        "sieben",
+        "acht",
+        "neun",
+        "zehn",
+        "elf",
+        "zwölf",
+        "dreizehn",
+        "vierzehn",
+        "fünfzehn",
+        "sechzehn",
+        "siebzehn",
+        "achtzehn",
+        "neunzehn",
+    ];
+    const TENS: [&str; 10] = [
+        "", "", "zwanzig", "dreißig", "vierzig", "fünfzig", "sechzig", "siebzig", "achtzig",
+        "neunzig",
+    ];
+
+    let mut words = String::new();
+    if n >= 100 {
+        words.push_str(ONES[(n / 100) as usize]);
+        words.push_str("hundert");
+    }
+
+    let rest = (n % 100) as usize;
+    if rest < 20 {
+        words.push_str(ONES[rest]);
+    } else {
+        if rest % 10 > 0 {
+            words.push_str(ONES[rest % 10]);
+            words.push_str("und");
+        }
+        words.push_str(TENS[rest / 10]);
+    }
+    words
+}
+
+fn english_number(n: u64) -> String {
+    if n == 0 {
+        return "zero".to_string();
+    }
+
+    let mut parts = Vec::new();
+    for (scale, name) in [
+        (1_000_000_000, " billion"),
+        (1_000_000, " million"),
+        (1_000, " thousand"),
+        (1, ""),
+    ] {
+        let count = n / scale % 1000;
+        if count > 0 {
+            parts.push(format!("{}{}", english_below_thousand(count), name));
+        }
+    }
+    parts.join(" ")
+}
+
+fn english_below_thousand(n: u64) -> String {
+    const ONES: [&str; 20] = [
+        "",
+        "one",
+        "two",
+        "three",
+        "four",
+        "five",
+        "six",
+        "seven",
+        "eight",
+        "nine",
+        "ten",
+        "eleven",
+        "twelve",
+        "thirteen",
+        "fourteen",
+        "fifteen",
+        "sixteen",
+        "seventeen",
+        "eighteen",
+        "nineteen",
+    ];
+    const TENS: [&str; 10] = [
+        "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+    ];
+
+    let mut parts = Vec::new();
+    if n >= 100 {
+        parts.push(format!("{} hundred", ONES[(n / 100) as usize]));
+    }
+
+    let rest = (n % 100) as usize;
+    if rest >= 20 {
+        if rest % 10 > 0 {
+            parts.push(format!("{}-{}", TENS[rest / 10], ONES[rest % 10]));
+        } else {
+            parts.push(TENS[rest / 10].to_string());
+        }
+    } else if rest > 0 {
+        parts.push(ONES[rest].to_string());
+    }
+    parts.join(" ")
+}
+
+/// Collapses runs of spaces and drops empty lines, keeping line breaks as sentence ends.
+fn collapse_whitespace(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(mention: Mention) -> Option<String> {
+        match mention {
+            Mention::User(id) if id.get() == 1 => Some("Alice".to_string()),
+            Mention::Role(id) if id.get() == 2 => Some("DJ".to_string()),
+            Mention::Channel(id) if id.get() == 3 => Some("music".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn mentions_are_resolved() {
+        assert_eq!(
+            mentions("<@1> and <@!1> are <@&2> in <#3>", resolve),
+            "Alice and Alice are DJ in music"
+        );
+        assert_eq!(mentions("hi <@99>!", resolve), "hi !");
+        assert_eq!(mentions("1 < 2 <b> <@x>", resolve), "1 < 2 <b> <@x>");
+    }
+
+    #[test]
+    fn urls_become_domains() {
+        assert_eq!(
+            urls("see https://www.example.com/a?b=c, or http://user@docs.rs:443/x"),
+            "see example.com, or docs.rs"
+        );
+        assert_eq!(urls("(https://example.org/path)"), "(example.org)");
+        assert_eq!(urls("no links here"), "no links here");
+    }
+
+    #[test]
+    fn markdown_is_dropped() {
+        assert_eq!(
+            markdown("# Title\n**bold** and *it* and __under__ ~~gone~~ ||spoiler||"),
+            "Title\nbold and it and under gone spoiler"
+        );
+        assert_eq!(markdown("> quote\n- item\n* item"), "quote\nitem\nitem");
+        assert_eq!(
+            markdown("run `cargo` then\n```rust\nfn main() {}\n```\ndone"),
+            "run cargo then\n\ndone"
+        );
+        assert_eq!(markdown("a [link](https://x.y) here"), "a link here");
+        assert_eq!(
+            markdown("keep snake_case and [brackets]"),
+            "keep snake_case and [brackets]"
+        );
+    }
+
+    #[test]
+    fn emoji_are_dropped_or_named() {
+        assert_eq!(emoji("hi 👋 <:pepe_hi:123>", false), "hi  ");
+        assert_eq!(emoji("hi 👋", true), "hi  waving hand ");
+        assert_eq!(emoji("<a:party_blob:5>", true), " party blob ");
+        assert_eq!(emoji("❤️👍", true), " red heart  thumbs up ");
+    }
+
+    #[test]
+    fn german_numbers() {
+        let german = |text| numbers(text, Language::German);
+        assert_eq!(
+            german("0 1 7 12 21 99"),
+            "null eins sieben zwölf einundzwanzig neunundneunzig"
+        );
+        assert_eq!(
+            german("101 1000 1001"),
+            "einhunderteins eintausend eintausendeins"
+        );
+        assert_eq!(
+            german("21000 999999"),
+            "einundzwanzigtausend neunhundertneunundneunzigtausendneunhundertneunundneunzig"
+        );
+        assert_eq!(
+            german("1000000 2000001"),
+            "eine Million zwei Millionen eins"
+        );
+        assert_eq!(german("3,5 1.000"), "drei Komma fünf eintausend");
+        assert_eq!(german("Seite 3."), "Seite drei.");
+    }
+
+    #[test]
+    fn english_numbers() {
+        let english = |text| numbers(text, Language::English);
+        assert_eq!(english("0 13 40 42"), "zero thirteen forty forty-two");
+        assert_eq!(english("105 1,000"), "one hundred five one thousand");
+        assert_eq!(english("2500000"), "two million five hundred thousand");
+        assert_eq!(english("3.14"), "three point one four");
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(
+            numbers("1 km and 5km/h", Language::German),
+            "ein Kilometer and fünf Kilometer pro Stunde"
+        );
+        assert_eq!(
+            numbers("1 h, 2 h", Language::German),
+            "eine Stunde, zwei Stunden"
+        );
+        assert_eq!(
+            numbers("$5 or 1€", Language::English),
+            "five dollars or one euro"
+        );
+        assert_eq!(
+            numbers("50% of 20 mins", Language::English),
+            "fifty percent of twenty mins"
+        );
+    }
+
+    #[test]
+    fn special_numbers() {
+        assert_eq!(numbers("mp3 4k", Language::English), "mp3 4k");
+        assert_eq!(numbers("007", Language::English), "zero zero seven");
+        assert_eq!(
+            numbers("1234567890123", Language::English),
+            "one two three four five six seven eight nine zero one two three"
+        );
+    }
+
+    #[test]
+    fn language_of_voice() {
+        assert_eq!(Language::of_voice("de_DE-lars"), Language::German);
+        assert_eq!(Language::of_voice("en_US-amy-medium"), Language::English);
+        assert_eq!(Language::of_voice("default"), Language::English);
+    }
+
+    #[test]
+    fn pipeline() {
+        let options = NormalizeOptions {
+            language: Language::English,
+            read_emoji: false,
+        };
+        assert_eq!(
+            normalize(
+                "**Hey** <@1>, check https://example.com/x 🎶\n\n  it's 2 min   long",
+                &options,
+                resolve
+            ),
+            "Hey Alice, check example.com\nit's two minutes long"
+        );
+    }
+}
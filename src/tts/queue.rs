@@ -0,0 +1,298 @@
+use crate::settings::{SettingsStore, SpeechMusic};
+use poise::serenity_prelude as serenity;
+use serenity::all::GuildId;
+use songbird::input::Input;
+use songbird::tracks::{PlayMode, TrackHandle};
+use songbird::{Call, Event, EventContext, EventHandler, TrackEvent};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+/// Music volume while speech plays on top of it, in percent of the normal volume.
+pub const DEFAULT_DUCK_VOLUME: u8 = 20;
+pub const DEFAULT_DUCK_FADE: Duration = Duration::from_millis(500);
+const FADE_STEP: Duration = Duration::from_millis(25);
+
+/// What was done to the music for the current run of speech.
+#[derive(Default)]
+enum MusicHold {
+    #[default]
+    Untouched,
+    Paused,
+    Ducked {
+        track: TrackHandle,
+        volume: f32,
+        fade: Duration,
+        fade_down: JoinHandle<()>,
+    },
+}
+
+/// The music fading back up after a run of speech.
+struct FadeUp {
+    task: JoinHandle<()>,
+    track: TrackHandle,
+    volume: f32,
+}
+
+/// Identifies a queued utterance, so its speaker can take back just that one.
+#[derive(Clone, Copy, PartialEq)]
+pub struct SpeechId(u64);
+
+/// Speech waiting to be spoken in one guild.
+#[derive(Default)]
+struct GuildSpeech {
+    pending: VecDeque<(SpeechId, Input)>,
+    current: Option<(SpeechId, TrackHandle)>,
+    music: MusicHold,
+    fade_up: Option<FadeUp>,
+}
+
+/// Plays speech one utterance after another per guild, pausing or ducking the music once for the whole run.
+pub struct SpeechQueue {
+    settings: Arc<SettingsStore>,
+    guilds: Mutex<HashMap<GuildId, GuildSpeech>>,
+    next_id: AtomicU64,
+}
+
+impl SpeechQueue {
+    pub fn new(settings: Arc<SettingsStore>) -> Self {
+        Self {
+            settings,
+            guilds: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Queues `speech`, it is spoken once everything queued before it has been.
+    pub async fn play(
+        self: &Arc<Self>,
+        guild_id: GuildId,
+        handler_lock: Arc<Mutex<Call>>,
+        speech: Input,
+    ) -> Result<SpeechId, &'static str> {
+        let id = SpeechId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let mut guilds = self.guilds.lock().await;
+        let state = guilds.entry(guild_id).or_default();
+
+        if let Some((_, current)) = &state.current {
+            // The track is gone without an end event, e.g. after the bot left the channel
+            if current.get_info().await.is_ok() {
+                state.pending.push_back((id, speech));
+                return Ok(id);
+            }
+            // Whatever was queued behind it belonged to the old connection
+            state.current = None;
+            state.pending.clear();
+        }
+
+        // Music held by a run that got lost still has to be restored afterwards
+        if matches!(state.music, MusicHold::Untouched) {
+            state.music = self.hold_music(guild_id, state, &handler_lock).await?;
+        }
+        self.start(guild_id, state, &handler_lock, id, speech).await;
+        Ok(id)
+    }
+
+    /// Stops the utterance being spoken, the next one starts right away.
+    /// Returns false if nothing is being spoken.
+    pub async fn skip(&self, guild_id: GuildId) -> bool {
+        let guilds = self.guilds.lock().await;
+        guilds
+            .get(&guild_id)
+            .and_then(|state| state.current.as_ref())
+            .is_some_and(|(_, current)| current.stop().is_ok())
+    }
+
+    /// Drops the utterance `id`, stopping it if it is being spoken. Everything else stays queued.
+    /// Returns false if it was spoken already.
+    pub async fn cancel(&self, guild_id: GuildId, id: SpeechId) -> bool {
+        let mut guilds = self.guilds.lock().await;
+        let Some(state) = guilds.get_mut(&guild_id) else {
+            return false;
+        };
+
+        if let Some(index) = state.pending.iter().position(|(queued, _)| *queued == id) {
+            state.pending.remove(index);
+            return true;
+        }
+        match &state.current {
+            Some((current_id, current)) if *current_id == id => current.stop().is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Number of utterances waiting behind the current one.
+    pub async fn pending(&self, guild_id: GuildId) -> usize {
+        let guilds = self.guilds.lock().await;
+        guilds.get(&guild_id).map_or(0, |state| state.pending.len())
+    }
+
+    async fn start(
+        self: &Arc<Self>,
+        guild_id: GuildId,
+        state: &mut GuildSpeech,
+        handler_lock: &Arc<Mutex<Call>>,
+        id: SpeechId,
+        speech: Input,
+    ) {
+        let handle = handler_lock.lock().await.play_input(speech);
+
+        let ended = SpeechEnded {
+            queue: self.clone(),
+            guild_id,
+            handler_lock: handler_lock.clone(),
+        };
+        let _ = handle.add_event(Event::Track(TrackEvent::End), ended.clone());
+        let _ = handle.add_event(Event::Track(TrackEvent::Error), ended);
+
+        state.current = Some((id, handle));
+    }
+
+    async fn finished(
+        self: &Arc<Self>,
+        guild_id: GuildId,
+        handler_lock: &Arc<Mutex<Call>>,
+        track: &TrackHandle,
+    ) {
+        let mut guilds = self.guilds.lock().await;
+        let Some(state) = guilds.get_mut(&guild_id) else {
+            return;
+        };
+
+        // Errors may be followed by an end event for the same track
+        if state
+            .current
+            .as_ref()
+            .is_none_or(|(_, current)| current.uuid() != track.uuid())
+        {
+            return;
+        }
+        state.current = None;
+
+        if let Some((id, next)) = state.pending.pop_front() {
+            self.start(guild_id, state, handler_lock, id, next).await;
+            return;
+        }
+
+        match std::mem::take(&mut state.music) {
+            MusicHold::Untouched => {}
+            MusicHold::Paused => {
+                let queue = handler_lock.lock().await.queue().clone();
+                let _ = queue.resume();
+            }
+            MusicHold::Ducked {
+                track,
+                volume,
+                fade,
+                fade_down,
+            } => {
+                fade_down.abort();
+                let from = track.get_info().await.map_or(volume, |info| info.volume);
+                state.fade_up = Some(FadeUp {
+                    task: tokio::spawn(fade_volume(track.clone(), from, volume, fade)),
+                    track,
+                    volume,
+                });
+            }
+        }
+    }
+
+    /// Pauses or ducks the music as configured for the guild.
+    async fn hold_music(
+        &self,
+        guild_id: GuildId,
+        state: &mut GuildSpeech,
+        handler_lock: &Arc<Mutex<Call>>,
+    ) -> Result<MusicHold, &'static str> {
+        let queue = handler_lock.lock().await.queue().clone();
+        let Some(track) = queue.current() else {
+            return Ok(MusicHold::Untouched);
+        };
+        let Ok(info) = track.get_info().await else {
+            return Ok(MusicHold::Untouched);
+        };
+        if !matches!(info.playing, PlayMode::Play) {
+            return Ok(MusicHold::Untouched);
+        }
+
+        let settings = self.settings.guild(guild_id).await;
+        match settings.speech_music {
+            SpeechMusic::Pause => {
+                if let Err(e) = queue.pause() {
+                    warn!("Failed to pause current track: {}", e);
+                    return Err("Failed to pause music.");
+                }
+                Ok(MusicHold::Paused)
+            }
+            SpeechMusic::Duck => {
+                // Still fading up from the last run, that one knows the real volume
+                let mut volume = info.volume;
+                if let Some(fade_up) = state.fade_up.take() {
+                    fade_up.task.abort();
+                    if fade_up.track.uuid() == track.uuid() {
+                        volume = fade_up.volume;
+                    }
+                }
+
+                let duck_volume = settings.duck_volume.unwrap_or(DEFAULT_DUCK_VOLUME);
+                let fade = settings
+                    .duck_fade_ms
+                    .map_or(DEFAULT_DUCK_FADE, Duration::from_millis);
+                let fade_down = tokio::spawn(fade_volume(
+                    track.clone(),
+                    info.volume,
+                    volume * duck_volume as f32 / 100.0,
+                    fade,
+                ));
+
+                Ok(MusicHold::Ducked {
+                    track,
+                    volume,
+                    fade,
+                    fade_down,
+                })
+            }
+        }
+    }
+}
+
+/// Moves the volume of `track` from `from` to `to` over `duration`.
+async fn fade_volume(track: TrackHandle, from: f32, to: f32, duration: Duration) {
+    let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+    for step in 1..=steps {
+        let volume = from + (to - from) * step as f32 / steps as f32;
+        if track.set_volume(volume).is_err() {
+            return;
+        }
+        if step < steps {
+            tokio::time::sleep(FADE_STEP).await;
+        }
+    }
+}
+
+/// Moves on to the next utterance once one has been spoken.
+#[derive(Clone)]
+struct SpeechEnded {
+    queue: Arc<SpeechQueue>,
+    guild_id: GuildId,
+    handler_lock: Arc<Mutex<Call>>,
+}
+
+#[serenity::async_trait]
+impl EventHandler for SpeechEnded {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(tracks) = ctx {
+            for (_, track) in *tracks {
+                self.queue
+                    .finished(self.guild_id, &self.handler_lock, track)
+                    .await;
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,248 @@
+use crate::settings::SettingsStore;
+use lru::LruCache;
+use piper_rs::PiperModel;
+use piper_rs::synth::PiperSpeechSynthesizer;
+use poise::serenity_prelude as serenity;
+use serenity::all::{GuildId, UserId};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
+use tokio::sync::Mutex;
+use tokio::task;
+use tracing::info;
+
+const MODELS_DIR: &str = "models";
+const DEFAULT_TTS_CONFIG_PATH: &str = "de_DE-lars.onnx.json";
+const CONFIG_SUFFIX: &str = ".onnx.json";
+/// Synthesizers kept in memory, the least recently used one is dropped first.
+const MAX_LOADED_VOICES: usize = 3;
+
+pub struct Voice {
+    pub synth: PiperSpeechSynthesizer,
+    pub sample_rate: u32,
+    model: Arc<dyn PiperModel + Send + Sync>,
+    /// Speaker set through `TTS_SPEAKER_ID` for the default voice, used unless markup picks another one.
+    default_speaker: Option<i64>,
+    /// The speaker is model state, so utterances switching it must not overlap.
+    speaker_lock: std::sync::Mutex<()>,
+}
+
+impl Voice {
+    pub fn default_speaker(&self) -> Option<i64> {
+        self.default_speaker
+    }
+
+    /// Runs `synthesize` with `speaker`, or the default speaker if `None`.
+    pub fn with_speaker<T>(&self, speaker: Option<i64>, synthesize: impl FnOnce() -> T) -> T {
+        let _guard = self.speaker_lock.lock().unwrap();
+        select_speaker(speaker, self.default_speaker, |sid| {
+            self.model.set_speaker(sid);
+        });
+        synthesize()
+    }
+}
+
+/// Sets the speaker of every utterance, also without markup, so one picked by `[speaker N]`
+/// doesn't stick to the shared model.
+fn select_speaker(
+    speaker: Option<i64>,
+    default_speaker: Option<i64>,
+    set_speaker: impl FnOnce(i64),
+) {
+    set_speaker(speaker.or(default_speaker).unwrap_or(0));
+}
+
+static LOADED_VOICES: LazyLock<Mutex<LruCache<String, Arc<Voice>>>> = LazyLock::new(|| {
+    Mutex::new(LruCache::new(
+        NonZeroUsize::new(MAX_LOADED_VOICES).expect("MAX_LOADED_VOICES must not be 0"),
+    ))
+});
+
+/// Held while a voice loads, so concurrent requests for it wait for a single load
+/// while other voices stay usable.
+type LoadLock = Arc<Mutex<()>>;
+
+static LOADING: LazyLock<std::sync::Mutex<HashMap<String, LoadLock>>> =
+    LazyLock::new(Default::default);
+
+fn voice_name(config_path: &Path) -> Option<String> {
+    config_path
+        .file_name()?
+        .to_str()?
+        .strip_suffix(CONFIG_SUFFIX)
+        .map(str::to_string)
+}
+
+fn default_config_path() -> PathBuf {
+    let config_path_raw = PathBuf::from(
+        env::var("TTS_CONFIG_PATH").unwrap_or_else(|_| DEFAULT_TTS_CONFIG_PATH.to_string()),
+    );
+    if config_path_raw.is_relative() {
+        let models_dir = PathBuf::from(MODELS_DIR);
+        if config_path_raw.starts_with(&models_dir) {
+            config_path_raw
+        } else {
+            models_dir.join(config_path_raw)
+        }
+    } else {
+        config_path_raw
+    }
+}
+
+/// The voice configured through `TTS_CONFIG_PATH`, used when nobody picked one.
+pub fn default_voice() -> String {
+    voice_name(&default_config_path()).unwrap_or_else(|| "default".to_string())
+}
+
+fn scan_dir(dir: &Path, voices: &mut BTreeMap<String, PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            scan_dir(&path, voices);
+        } else if let Some(name) = voice_name(&path) {
+            voices.entry(name).or_insert(path);
+        }
+    }
+}
+
+/// Finds every piper voice config below `./models`, keyed by voice name.
+async fn scan() -> BTreeMap<String, PathBuf> {
+    task::spawn_blocking(|| {
+        let mut voices = BTreeMap::new();
+        scan_dir(Path::new(MODELS_DIR), &mut voices);
+
+        // The default voice may live outside of `./models`
+        let default_path = default_config_path();
+        if default_path.exists() {
+            voices.insert(default_voice(), default_path);
+        }
+        voices
+    })
+    .await
+    .unwrap_or_default()
+}
+
+/// Names of all voices that can be used, sorted alphabetically.
+pub async fn available() -> Vec<String> {
+    scan().await.into_keys().collect()
+}
+
+/// Returns the synthesizer of `name`, loading it if it isn't cached yet.
+pub async fn get(name: &str) -> Result<Arc<Voice>, String> {
+    if let Some(voice) = cached(name).await {
+        return Ok(voice);
+    }
+
+    let loading = LOADING
+        .lock()
+        .unwrap()
+        .entry(name.to_string())
+        .or_default()
+        .clone();
+    let _loading = loading.lock().await;
+    // Loaded by whoever held the lock before
+    if let Some(voice) = cached(name).await {
+        return Ok(voice);
+    }
+
+    let Some(config_path) = scan().await.remove(name) else {
+        return Err(format!(
+            "TTS voice `{}` not found. Place your model files in `./models` and set `TTS_CONFIG_PATH` if needed.",
+            name
+        ));
+    };
+
+    // Speaker ids differ between models, the setting is meant for the default voice only
+    let speaker_id = if name == default_voice() {
+        env::var("TTS_SPEAKER_ID").ok()
+    } else {
+        None
+    };
+
+    let voice = task::spawn_blocking(move || {
+        let model = piper_rs::from_config_path(&config_path)
+            .map_err(|e| format!("Failed to load model: {}", e))?;
+
+        let default_speaker = speaker_id
+            .map(|sid| sid.parse::<i64>())
+            .transpose()
+            .map_err(|_| "TTS_SPEAKER_ID must be a number".to_string())?;
+
+        let sample_rate = model
+            .audio_output_info()
+            .map_err(|e| format!("Failed to read audio format: {}", e))?
+            .sample_rate as u32;
+
+        let synth = PiperSpeechSynthesizer::new(model.clone())
+            .map_err(|e| format!("Failed to create synthesizer: {}", e))?;
+
+        Ok::<_, String>(Voice {
+            synth,
+            sample_rate,
+            model,
+            default_speaker,
+            speaker_lock: std::sync::Mutex::new(()),
+        })
+    })
+    .await
+    .map_err(|e| format!("Failed to load voice: {}", e))??;
+
+    info!("Loaded TTS voice `{}`", name);
+    let voice = Arc::new(voice);
+    LOADED_VOICES
+        .lock()
+        .await
+        .put(name.to_string(), voice.clone());
+    Ok(voice)
+}
+
+async fn cached(name: &str) -> Option<Arc<Voice>> {
+    LOADED_VOICES.lock().await.get(name).cloned()
+}
+
+/// Picks the voice for `user_id`: their own choice, then the guild's, then the default.
+pub async fn resolve(
+    settings: &SettingsStore,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+) -> String {
+    if let Some(voice) = settings.user(user_id).await.voice {
+        return voice;
+    }
+
+    if let Some(guild_id) = guild_id {
+        if let Some(voice) = settings.guild(guild_id).await.voice {
+            return voice;
+        }
+    }
+
+    default_voice()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn restores_the_default_speaker_after_markup() {
+        let current = Cell::new(None);
+        let set_speaker = |sid| current.set(Some(sid));
+
+        select_speaker(Some(3), None, set_speaker);
+        assert_eq!(current.get(), Some(3));
+        select_speaker(None, None, set_speaker);
+        assert_eq!(current.get(), Some(0));
+
+        select_speaker(Some(3), Some(1), set_speaker);
+        assert_eq!(current.get(), Some(3));
+        select_speaker(None, Some(1), set_speaker);
+        assert_eq!(current.get(), Some(1));
+    }
+}